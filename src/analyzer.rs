@@ -1,12 +1,169 @@
-use crate::types::PackageJson;
-use git2::{DiffDelta, DiffLine, DiffOptions, Repository};
+use crate::{directive::Directives, types::PackageJson};
+use git2::{Delta, Diff, DiffFile, DiffFindOptions, DiffOptions, Oid, Repository, Sort};
 use regex::Regex;
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fmt,
+    io::{self, ErrorKind},
+    path::Path,
+    str,
+};
 use tokio::fs;
 
+#[derive(Debug)]
+pub enum AnalyzerError {
+    /// Failed to read data from Git repository.
+    Repository(git2::Error),
+    /// Path of a changed file can't be recognized.
+    UnrecognizedPath(String),
+    /// Failed to read files of plugins.
+    Io(io::Error),
+    /// A plugin mentioned in directives doesn't exist.
+    UnknownPlugin(String),
+    /// The `package.json` of a plugin can't be parsed.
+    MalformedManifest {
+        plugin: String,
+        source: serde_json::Error,
+    },
+}
+
+impl fmt::Display for AnalyzerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnalyzerError::Repository(e) => write!(f, "failed to read repository: {e}"),
+            AnalyzerError::Io(e) => write!(f, "failed to read plugin files: {e}"),
+            AnalyzerError::UnrecognizedPath(path) => write!(f, "unrecognized path: {path}"),
+            AnalyzerError::UnknownPlugin(name) => write!(f, "unknown plugin: {name}"),
+            AnalyzerError::MalformedManifest { plugin, source } => {
+                write!(f, "malformed 'package.json' of plugin '{plugin}': {source}")
+            }
+        }
+    }
+}
+
+impl Error for AnalyzerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AnalyzerError::Repository(e) => Some(e),
+            AnalyzerError::Io(e) => Some(e),
+            AnalyzerError::UnrecognizedPath(_) | AnalyzerError::UnknownPlugin(_) => None,
+            AnalyzerError::MalformedManifest { source, .. } => Some(source),
+        }
+    }
+}
+
+impl From<git2::Error> for AnalyzerError {
+    fn from(e: git2::Error) -> Self {
+        AnalyzerError::Repository(e)
+    }
+}
+
+impl From<io::Error> for AnalyzerError {
+    fn from(e: io::Error) -> Self {
+        AnalyzerError::Io(e)
+    }
+}
+
+/// A change of a single plugin, keyed by the plugin name in [`ChangeSet`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// A new plugin directory was added.
+    Added { version: String },
+    /// The version of an existing plugin was changed.
+    Updated { version: String },
+    /// The plugin directory was deleted.
+    Removed,
+    /// The plugin directory was renamed from another name.
+    Renamed { from: String, version: String },
+}
+
+impl Change {
+    /// The version which should be released, if any.
+    pub fn version(&self) -> Option<&String> {
+        match self {
+            Change::Added { version }
+            | Change::Updated { version }
+            | Change::Renamed { version, .. } => Some(version),
+            Change::Removed => None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ChangeSet {
+    changes: BTreeMap<String, Change>,
+    forced: BTreeSet<String>,
+}
+
+impl ChangeSet {
+    pub fn get(&self, name: &str) -> Option<&Change> {
+        self.changes.get(name)
+    }
+
+    pub fn insert(&mut self, name: String, change: Change) {
+        self.changes.insert(name, change);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Change> {
+        self.forced.remove(name);
+        self.changes.remove(name)
+    }
+
+    pub fn clear(&mut self) {
+        self.changes.clear();
+        self.forced.clear();
+    }
+
+    /// Whether the plugin is requested to update by a `force update` directive.
+    pub fn is_forced(&self, name: &str) -> bool {
+        self.forced.contains(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Change)> {
+        self.changes.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Plugins that need to be built and released, with their new versions.
+    pub fn to_build(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.changes
+            .iter()
+            .filter_map(|(name, change)| change.version().map(|version| (name, version)))
+    }
+}
+
+/// A commit in the analyzed range.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Commit {
+    pub message: String,
+    /// Plugins whose versions were changed by this commit itself.
+    /// It's always empty for merge commits, since their changes are made by merged commits.
+    pub bumped: BTreeSet<String>,
+}
+
+/// Collect changes of plugins between `base` and `HEAD`.
+///
+/// When `base` is `None` or empty, only the latest commit is analyzed
+/// (compared with its first parent).
+/// Otherwise `base` can be any revision accepted by `git rev-parse`, such as a branch name
+/// or the SHA of the previous successful build, and all changes made in the range
+/// `base..HEAD` are collected. Since the trees of both ends are compared directly,
+/// the latest version of each plugin wins.
+///
+/// All commits in the range are returned as well (newest first),
+/// including commits brought in by merge commits, so directives in them can be honored.
 pub fn analyze(
     repo_path: impl AsRef<Path>,
-) -> Result<(String, HashMap<String, String>), git2::Error> {
+    base: Option<&str>,
+) -> Result<(Vec<Commit>, ChangeSet), AnalyzerError> {
     info!(
         "Reading repository at '{}'...",
         repo_path.as_ref().display()
@@ -17,92 +174,338 @@ pub fn analyze(
     let commit_msg = head.message().unwrap_or("(No commit message.)").trim();
     info!("Head commit is {}: {commit_msg}", head.id());
 
+    let base = match base.filter(|base| !base.trim().is_empty()) {
+        Some(base) => {
+            let commit = repo.revparse_single(base)?.peel_to_commit()?;
+            info!("Base commit is {} ({base}).", commit.id());
+            commit
+        }
+        None => head.parent(0)?,
+    };
+
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+    revwalk.push(head.id())?;
+    revwalk.hide(base.id())?;
+    let re_plugin_name = Regex::new(r"^plugins/([\w-]+)/package\.json$").unwrap();
+    let commits = revwalk
+        .map(|id| {
+            let commit = repo.find_commit(id?)?;
+            Ok(Commit {
+                message: commit.message().unwrap_or_default().trim().to_owned(),
+                bumped: bumped_plugins(&repo, &commit, &re_plugin_name)?,
+            })
+        })
+        .collect::<Result<Vec<_>, AnalyzerError>>()?;
+    info!("Found {} commit(s) since {}.", commits.len(), base.id());
+
     let mut opts = DiffOptions::new();
     opts.include_untracked(true).ignore_filemode(true);
 
-    let diff = repo.diff_tree_to_tree(
-        Some(head.parent(0)?.tree()?).as_ref(),
+    let mut diff = repo.diff_tree_to_tree(
+        Some(base.tree()?).as_ref(),
         Some(head.tree()?).as_ref(),
         Some(&mut opts),
     )?;
+    diff.find_similar(Some(DiffFindOptions::new().renames(true)))?;
+
+    let mut changes = ChangeSet::default();
+
+    let plugin_name = |file: &DiffFile| -> Result<Option<String>, AnalyzerError> {
+        if file.id().is_zero() {
+            return Ok(None);
+        }
+
+        let path = file
+            .path_bytes()
+            .ok_or_else(|| AnalyzerError::UnrecognizedPath(file.id().to_string()))?;
+        let is_manifest = path.ends_with(b"package.json");
+        let path = match str::from_utf8(path) {
+            Ok(path) => path,
+            Err(_) if is_manifest => {
+                return Err(AnalyzerError::UnrecognizedPath(
+                    String::from_utf8_lossy(path).into(),
+                ))
+            }
+            Err(_) => {
+                warn!(
+                    "Skipped '{}' since its path isn't valid UTF-8.",
+                    String::from_utf8_lossy(path)
+                );
+                return Ok(None);
+            }
+        };
+        if !is_manifest {
+            return Ok(None);
+        }
 
-    let mut map = HashMap::new();
-
-    let re_plugin_name = Regex::new(r"plugins/([\w-]+)/package\.json").unwrap();
-    let re_version = Regex::new(r#""version": "([\w\.]+)""#).unwrap();
-
-    info!("Analyzing diff of latest commit...");
-    diff.foreach(
-        &mut |_, _| true,
-        None,
-        None,
-        Some(&mut |delta: DiffDelta, _, line: DiffLine| {
-            let file = delta.new_file();
-            let path = file
-                .path_bytes()
-                .map(String::from_utf8_lossy) // we assumed path doesn't contain special chars
-                .or_else(|| {
-                    error!("Cannot get the path of object {}.", file.id());
-                    None
-                })
-                .unwrap();
-            if path.ends_with("package.json") && line.origin() == '+' {
-                let content = String::from_utf8_lossy(line.content());
-                if let Some(caps) = re_version.captures(&content) {
-                    let plugin_name = re_plugin_name
-                        .captures(&path)
-                        .or_else(|| {
-                            error!("Cannot extract plugin name from path: {path}");
-                            None
-                        })
-                        .unwrap()
-                        .get(1)
-                        .or_else(|| {
-                            error!("Cannot extract plugin name from path: {path}");
-                            None
-                        })
-                        .unwrap()
-                        .as_str()
-                        .to_owned();
-                    let version = caps
-                        .get(1)
-                        .or_else(|| {
-                            error!("Cannot extract version of plugin \"{plugin_name}\"");
-                            None
-                        })
-                        .unwrap()
-                        .as_str()
-                        .to_owned();
-
-                    info!("Version changed: {plugin_name} -> {version}");
-
-                    map.insert(plugin_name, version);
+        let name = re_plugin_name
+            .captures(path)
+            .and_then(|caps| caps.get(1))
+            .map(|name| name.as_str().to_owned());
+        if name.is_none() {
+            warn!("Skipped '{path}' since it doesn't belong to any plugin.");
+        }
+        Ok(name)
+    };
+
+    info!("Analyzing diff between {} and {}...", base.id(), head.id());
+    for delta in diff.deltas() {
+        let old_file = delta.old_file();
+        let new_file = delta.new_file();
+        let old_name = plugin_name(&old_file)?;
+        let new_name = plugin_name(&new_file)?;
+
+        let (name, change) = match (old_name, new_name) {
+            (Some(old_name), None) => {
+                info!("Plugin removed: {old_name}");
+                changes.insert(old_name, Change::Removed);
+                continue;
+            }
+            (old_name, Some(new_name)) => {
+                let version = read_manifest(&repo, new_file.id(), &new_name)?.version;
+
+                match old_name {
+                    Some(old_name) if old_name != new_name => {
+                        if !moved_with_files(&diff, &old_name, &new_name) {
+                            info!("Plugin removed: {old_name}");
+                            changes.insert(old_name, Change::Removed);
+                            info!("Plugin added: {new_name} ({version})");
+                            changes.insert(new_name, Change::Added { version });
+                            continue;
+                        }
+                        info!("Plugin renamed: {old_name} -> {new_name} ({version})");
+                        (
+                            new_name,
+                            Change::Renamed {
+                                from: old_name,
+                                version,
+                            },
+                        )
+                    }
+                    Some(_) => {
+                        match read_manifest(&repo, old_file.id(), &new_name) {
+                            Ok(manifest) if manifest.version == version => continue,
+                            Err(AnalyzerError::MalformedManifest { .. }) => {
+                                warn!(
+                                    "Previous 'package.json' of plugin '{new_name}' is malformed."
+                                );
+                            }
+                            Ok(_) => {}
+                            Err(e) => return Err(e),
+                        }
+                        info!("Version changed: {new_name} -> {version}");
+                        (new_name, Change::Updated { version })
+                    }
+                    None => {
+                        info!("Plugin added: {new_name} ({version})");
+                        (new_name, Change::Added { version })
+                    }
                 }
             }
-            true
-        }),
-    )?;
+            (None, None) => continue,
+        };
+
+        changes.insert(name, change);
+    }
 
-    Ok((commit_msg.to_owned(), map))
+    Ok((commits, changes))
 }
 
-pub async fn analyze_commit_message(
-    message: &str,
+/// Find plugins whose versions were changed by a commit, compared with its parent.
+///
+/// Merge commits are skipped, since the changes they bring are made by merged commits.
+fn bumped_plugins(
+    repo: &Repository,
+    commit: &git2::Commit,
+    re_plugin_name: &Regex,
+) -> Result<BTreeSet<String>, AnalyzerError> {
+    let mut bumped = BTreeSet::new();
+    let parent = match commit.parent_count() {
+        0 => None,
+        1 => Some(commit.parent(0)?.tree()?),
+        _ => return Ok(bumped),
+    };
+
+    let mut opts = DiffOptions::new();
+    opts.pathspec("plugins/*/package.json");
+    let diff = repo.diff_tree_to_tree(parent.as_ref(), Some(&commit.tree()?), Some(&mut opts))?;
+    for delta in diff.deltas() {
+        let (old_file, new_file) = (delta.old_file(), delta.new_file());
+        let name = new_file
+            .path()
+            .and_then(Path::to_str)
+            .and_then(|path| re_plugin_name.captures(path))
+            .and_then(|caps| caps.get(1));
+        let Some(name) = name.map(|name| name.as_str()) else {
+            continue;
+        };
+        if new_file.id().is_zero() {
+            continue;
+        }
+
+        let version = |id| {
+            read_manifest(repo, id, name)
+                .ok()
+                .map(|manifest| manifest.version)
+        };
+        if old_file.id().is_zero() || version(old_file.id()) != version(new_file.id()) {
+            bumped.insert(name.to_owned());
+        }
+    }
+
+    Ok(bumped)
+}
+
+/// Check whether most files of a plugin were moved to the directory of another plugin.
+///
+/// Manifests of plugins are alike, so they're often paired as renamed by Git
+/// even if the plugins are unrelated. Other files must follow the manifest to make it a rename.
+fn moved_with_files(diff: &Diff, old_name: &str, new_name: &str) -> bool {
+    let old_dir = format!("plugins/{old_name}/");
+    let new_dir = format!("plugins/{new_name}/");
+    let manifest = format!("{old_dir}package.json");
+
+    let (mut total, mut moved) = (0, 0);
+    for delta in diff.deltas() {
+        let Some(old_path) = delta.old_file().path().and_then(Path::to_str) else {
+            continue;
+        };
+        if !old_path.starts_with(&old_dir) || old_path == manifest {
+            continue;
+        }
+        match delta.status() {
+            Delta::Deleted => total += 1,
+            Delta::Renamed => {
+                total += 1;
+                let new_path = delta.new_file().path().and_then(Path::to_str);
+                if new_path.is_some_and(|path| path.starts_with(&new_dir)) {
+                    moved += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    moved * 2 > total
+}
+
+/// Load and parse the `package.json` blob with the given id.
+fn read_manifest(repo: &Repository, id: Oid, plugin: &str) -> Result<PackageJson, AnalyzerError> {
+    let blob = repo.find_blob(id)?;
+    serde_json::from_slice::<PackageJson>(blob.content()).map_err(|source| {
+        AnalyzerError::MalformedManifest {
+            plugin: plugin.to_owned(),
+            source,
+        }
+    })
+}
+
+/// Apply directives in messages of analyzed commits to the change set.
+///
+/// Skips are scoped to the commits giving them, see [`Directives::parse_commits`].
+pub async fn analyze_commits(
+    commits: &[Commit],
     root: impl AsRef<Path>,
-    plugins: &mut HashMap<String, String>,
-) -> anyhow::Result<()> {
-    let re_force_update = Regex::new(r"force update: ([\w-]+)").unwrap();
-    let plugin_name = re_force_update.captures(message).and_then(|s| s.get(1));
-    if let Some(name) = plugin_name {
-        let package_json = fs::read_to_string(format!(
-            "{}/plugins/{}/package.json",
-            root.as_ref().display(),
-            name.as_str()
-        ))
-        .await?;
-        let info = serde_json::from_str::<PackageJson>(&package_json)?;
-        plugins.insert(name.as_str().to_owned(), info.version);
+    changes: &mut ChangeSet,
+) -> Result<(), AnalyzerError> {
+    let directives = Directives::parse_commits(
+        commits
+            .iter()
+            .map(|commit| (commit.message.as_str(), &commit.bumped)),
+    );
+    apply_directives(&directives, root, changes).await
+}
+
+/// Apply directives in commit messages to the change set.
+pub async fn analyze_commit_messages<S: AsRef<str>>(
+    messages: impl IntoIterator<Item = S>,
+    root: impl AsRef<Path>,
+    changes: &mut ChangeSet,
+) -> Result<(), AnalyzerError> {
+    apply_directives(&Directives::parse_all(messages), root, changes).await
+}
+
+pub async fn apply_directives(
+    directives: &Directives,
+    root: impl AsRef<Path>,
+    changes: &mut ChangeSet,
+) -> Result<(), AnalyzerError> {
+    if directives.skip_run {
+        info!("Found '[nano skip]' directive. Nothing will be released.");
+        changes.clear();
+        return Ok(());
+    }
+
+    let plugins_dir = format!("{}/plugins", root.as_ref().display());
+
+    let force_update = if directives.force_update_all {
+        info!("All plugins will be forced to update.");
+        list_plugins(&root).await?
+    } else {
+        directives.force_update.clone()
+    };
+
+    for name in force_update {
+        if changes.get(&name).and_then(Change::version).is_some() {
+            changes.forced.insert(name);
+            continue;
+        }
+
+        let version = plugin_version(&root, &name).await?;
+
+        info!("Plugin '{name}' will be forced to update.");
+        changes.forced.insert(name.clone());
+        changes.insert(name, Change::Updated { version });
+    }
+
+    for name in &directives.skip_build {
+        if changes.remove(name).is_some() {
+            info!("Plugin '{name}' won't be built as requested.");
+        } else if fs::metadata(format!("{plugins_dir}/{name}")).await.is_err() {
+            return Err(AnalyzerError::UnknownPlugin(name.clone()));
+        }
     }
 
     Ok(())
 }
+
+/// Time of the head commit, as seconds since Unix epoch.
+pub fn head_commit_time(repo_path: impl AsRef<Path>) -> Result<i64, AnalyzerError> {
+    let repo = Repository::open(repo_path)?;
+    let head = repo.head()?.peel_to_commit()?;
+    Ok(head.time().seconds())
+}
+
+/// Read current version of a plugin from its `package.json` in working tree.
+pub async fn plugin_version(root: impl AsRef<Path>, name: &str) -> Result<String, AnalyzerError> {
+    let path = format!("{}/plugins/{name}/package.json", root.as_ref().display());
+    let package_json = match fs::read(path).await {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Err(AnalyzerError::UnknownPlugin(name.to_owned()))
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let info = serde_json::from_slice::<PackageJson>(&package_json).map_err(|source| {
+        AnalyzerError::MalformedManifest {
+            plugin: name.to_owned(),
+            source,
+        }
+    })?;
+
+    Ok(info.version)
+}
+
+/// List names of all plugins in working tree.
+pub async fn list_plugins(root: impl AsRef<Path>) -> io::Result<BTreeSet<String>> {
+    let mut names = BTreeSet::new();
+    let mut dirs = fs::read_dir(format!("{}/plugins", root.as_ref().display())).await?;
+    while let Some(dir) = dirs.next_entry().await? {
+        if fs::metadata(dir.path().join("package.json")).await.is_ok() {
+            names.insert(dir.file_name().to_string_lossy().into_owned());
+        }
+    }
+
+    Ok(names)
+}
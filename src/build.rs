@@ -1,109 +1,228 @@
-use futures::future::join_all;
+use crate::{
+    config::{BuildConfig, BuildMode, PackageManager},
+    process,
+    types::PackageJson,
+};
 use std::{
     io::{Error, ErrorKind},
     path::Path,
 };
 use tokio::{fs, io::Result, process::Command};
 
-async fn pnpm(root: impl AsRef<Path>) -> Result<()> {
-    info!("Running pnpm to install dependencies...");
-
-    let output = Command::new("pnpm")
-        .arg("i")
-        .current_dir(root)
-        .output()
-        .await?;
-    let status = output.status;
-    if !status.success() {
-        let code = status.code().unwrap_or(-1);
-        error!(
-            "Failed to run pnpm to install dependencies. Detail: {}",
-            String::from_utf8_lossy(&output.stdout)
-        );
-        return Err(Error::new(ErrorKind::Other, format!("exit code: {code}")));
-    }
-
-    Ok(())
+/// Scope of install and build commands.
+enum Scope<'a> {
+    /// The whole repository.
+    Workspace,
+    /// A plugin and its dependencies in workspace.
+    Plugin { name: &'a str, package: String },
 }
 
-async fn webpack(root: impl AsRef<Path>) -> Result<()> {
-    info!("Running webpack...");
+impl Scope<'_> {
+    fn plugin(&self) -> Option<&str> {
+        match self {
+            Scope::Workspace => None,
+            Scope::Plugin { name, .. } => Some(name),
+        }
+    }
 
-    let output = Command::new("pnpm")
-        .arg("build")
-        .current_dir(root)
-        .env("NODE_ENV", "production")
-        .output()
-        .await?;
-    let status = output.status;
-    if !status.success() {
-        let code = status.code().unwrap_or(-1);
-        error!(
-            "Failed to run webpack. Detail: {}",
-            String::from_utf8_lossy(&output.stdout)
-        );
-        return Err(Error::new(ErrorKind::Other, format!("exit code: {code}")));
+    fn package(&self) -> Option<&str> {
+        match self {
+            Scope::Workspace => None,
+            Scope::Plugin { package, .. } => Some(package),
+        }
     }
 
-    Ok(())
+    fn filter(&self) -> Option<String> {
+        match self {
+            Scope::Workspace => None,
+            // Trailing "..." selects the package and all its dependencies in workspace.
+            Scope::Plugin { package, .. } => Some(format!("{package}...")),
+        }
+    }
 }
 
-async fn remove_source_files(path: impl AsRef<Path>) -> Result<()> {
-    let mut items = fs::read_dir(&path).await?;
-    while let Some(item) = items.next_entry().await? {
-        let path = item.path();
-        let file_type = item.file_type().await?;
-        if file_type.is_file() && path.extension().map(is_source_file).unwrap_or_default() {
-            fs::remove_file(path).await?;
-        }
+/// Detect package manager of the repository.
+///
+/// The one specified in config takes precedence.
+/// Otherwise, it's detected by lockfile at repository root, and falls back to pnpm.
+pub async fn detect_package_manager(
+    root: impl AsRef<Path>,
+    config: &BuildConfig,
+) -> PackageManager {
+    if let Some(package_manager) = config.package_manager {
+        return package_manager;
     }
 
-    Ok(())
+    for package_manager in PackageManager::ALL {
+        if fs::metadata(root.as_ref().join(package_manager.lockfile()))
+            .await
+            .is_ok()
+        {
+            return package_manager;
+        }
+    }
+    PackageManager::Pnpm
 }
 
-fn is_source_file(ext: &std::ffi::OsStr) -> bool {
-    ext == "ts" || ext == "tsx" || ext == "scss"
+/// Install dependencies at repository root.
+///
+/// For a plugin scope, only the plugin and its dependencies will be installed,
+/// except for yarn which doesn't support that.
+async fn install(
+    root: impl AsRef<Path>,
+    package_manager: PackageManager,
+    scope: &Scope<'_>,
+    config: &BuildConfig,
+    log_dir: &Path,
+) -> Result<()> {
+    let program = package_manager.program();
+    info!("Running {program} to install dependencies...");
+
+    let mut command = Command::new(program);
+    command.current_dir(root);
+    let frozen = config.frozen_lockfile;
+    match package_manager {
+        PackageManager::Pnpm => {
+            command.arg("install");
+            if frozen {
+                command.arg("--frozen-lockfile");
+            }
+            if let Some(filter) = scope.filter() {
+                command.arg("--filter").arg(filter);
+            }
+        }
+        PackageManager::Yarn => {
+            command.arg("install");
+            if frozen {
+                command.arg("--frozen-lockfile");
+            }
+        }
+        PackageManager::Npm => {
+            command.arg(if frozen { "ci" } else { "install" });
+            if let Some(package) = scope.package() {
+                command.arg("--workspace").arg(package);
+            }
+        }
+    }
+    process::run(
+        &mut command,
+        &format!("{program}-install"),
+        scope.plugin(),
+        Some(log_dir),
+        config.install_timeout(),
+    )
+    .await
 }
 
-pub async fn clean_up(path: impl AsRef<Path>) {
-    let path = path.as_ref().display();
-
-    let node_modules = format!("{path}/node_modules");
-    if fs::File::open(&node_modules).await.is_ok()
-        && fs::remove_dir_all(&node_modules).await.is_err()
-    {
-        warn!("Failed to clean 'node_modules' directory at '{path}'.");
-    }
+/// Run `build` script at repository root.
+///
+/// For a plugin scope, build script of the plugin will be run instead.
+/// pnpm also runs build scripts of its dependencies in workspace,
+/// and packages without build script will be skipped.
+async fn webpack(
+    root: impl AsRef<Path>,
+    package_manager: PackageManager,
+    scope: &Scope<'_>,
+    config: &BuildConfig,
+    log_dir: &Path,
+) -> Result<()> {
+    info!("Running webpack...");
 
-    let git_ignore = format!("{path}/.gitignore");
-    if fs::File::open(&git_ignore).await.is_ok() && fs::remove_file(&git_ignore).await.is_err() {
-        warn!("Failed to delete '.gitignore' file at '{path}'.");
+    let mut command = Command::new(package_manager.program());
+    command.current_dir(root).env("NODE_ENV", "production");
+    match (package_manager, scope.package()) {
+        (_, None) => {
+            command.args(["run", "build"]);
+        }
+        (PackageManager::Pnpm, Some(_)) => {
+            let filter = scope.filter().unwrap_or_default();
+            command
+                .arg("--filter")
+                .arg(filter)
+                .args(["run", "--if-present", "build"]);
+        }
+        (PackageManager::Yarn, Some(package)) => {
+            command.arg("workspace").arg(package).args(["run", "build"]);
+        }
+        (PackageManager::Npm, Some(package)) => {
+            command
+                .args(["run", "build", "--if-present", "--workspace"])
+                .arg(package);
+        }
     }
+    process::run(
+        &mut command,
+        "webpack",
+        scope.plugin(),
+        Some(log_dir),
+        config.build_timeout(),
+    )
+    .await
+}
 
-    let source_files = format!("{path}/assets");
-    if fs::File::open(&source_files).await.is_ok()
-        && remove_source_files(&source_files).await.is_err()
-    {
-        warn!("Failed to clean source files at '{path}'.");
-    }
+/// Build a single plugin with its workspace dependencies.
+async fn build_plugin(
+    root: impl AsRef<Path>,
+    name: &str,
+    package_manager: PackageManager,
+    config: &BuildConfig,
+    log_dir: &Path,
+) -> Result<()> {
+    let manifest = fs::read(format!(
+        "{}/plugins/{name}/package.json",
+        root.as_ref().display()
+    ))
+    .await?;
+    let manifest = serde_json::from_slice::<PackageJson>(&manifest)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    let scope = Scope::Plugin {
+        name,
+        package: manifest.name,
+    };
+
+    install(&root, package_manager, &scope, config, log_dir).await?;
+    webpack(&root, package_manager, &scope, config, log_dir).await
 }
 
 pub async fn build<S: AsRef<str>>(
     root: impl AsRef<Path>,
     plugins: impl Iterator<Item = (S, S)>,
+    config: &BuildConfig,
+    log_dir: &Path,
 ) -> Result<()> {
-    pnpm(&root).await?;
-    webpack(&root).await?;
-
-    let root = root.as_ref();
-
-    let cleans = plugins.map(|(name, _)| {
-        let name = name.as_ref();
-        info!("Cleaning up for plugin '{name}'...");
-        let path = format!("{}/plugins/{name}", root.display());
-        async move { clean_up(&path).await }
-    });
-    join_all(cleans.collect::<Vec<_>>()).await;
+    let plugins = plugins
+        .map(|(name, _)| name.as_ref().to_owned())
+        .collect::<Vec<_>>();
+
+    let package_manager = detect_package_manager(&root, config).await;
+    match config.mode {
+        BuildMode::Monorepo => {
+            let scope = Scope::Workspace;
+            install(&root, package_manager, &scope, config, log_dir).await?;
+            webpack(&root, package_manager, &scope, config, log_dir).await?;
+        }
+        BuildMode::PerPlugin => {
+            let mut failed = vec![];
+            // Package managers share the lockfile and store between packages,
+            // so don't run them in parallel.
+            for name in &plugins {
+                info!("Building plugin '{name}'...");
+                match build_plugin(&root, name, package_manager, config, log_dir).await {
+                    Ok(_) => info!("Built plugin '{name}' successfully."),
+                    Err(e) => {
+                        error!("Failed to build plugin '{name}': {e}");
+                        failed.push(name.as_str());
+                    }
+                }
+            }
+            if !failed.is_empty() {
+                return Err(Error::other(format!(
+                    "failed to build plugin(s): {}",
+                    failed.join(", ")
+                )));
+            }
+        }
+    }
 
     Ok(())
 }
@@ -1,18 +1,10 @@
-use futures::{
-    future::{try_join3, try_join_all},
-    stream::StreamExt,
-};
+use crate::{config::ComposerConfig, process};
+use futures::future::try_join_all;
 use reqwest::ClientBuilder;
 use serde::Deserialize;
 use serde_json::from_slice;
-use std::{
-    collections::HashSet,
-    env,
-    io::{Error, ErrorKind},
-    path::{self, Path},
-};
+use std::{collections::HashSet, env, io::ErrorKind, path::Path, time::Duration};
 use tokio::{fs, io::Result, process::Command};
-use tokio_stream::wrappers::ReadDirStream;
 
 #[derive(Deserialize)]
 struct ComposerLock {
@@ -33,10 +25,7 @@ pub async fn parse_lock(path: impl AsRef<Path>) -> Result<ComposerPackages> {
 
     let json = fs::read(&path).await?;
     let lock = from_slice::<ComposerLock>(&json)
-        .map_err(|e| {
-            error!("Failed to parse composer.lock ({}).", path);
-            e
-        })
+        .inspect_err(|_| error!("Failed to parse composer.lock ({}).", path))
         .unwrap();
 
     let packages = lock
@@ -47,33 +36,33 @@ pub async fn parse_lock(path: impl AsRef<Path>) -> Result<ComposerPackages> {
     Ok(packages)
 }
 
-pub async fn run_composer(path: impl AsRef<Path>) -> Result<()> {
-    info!("Running Composer at '{}'...", path.as_ref().display());
-    let output = Command::new("composer")
-        .arg("install")
-        .arg("--no-dev")
-        .current_dir(&path)
-        .output()
-        .await?;
-
-    let status = output.status;
-    if !status.success() {
-        let code = status.code().unwrap_or(-1);
-        error!(
-            "Failed to run Composer at '{}'. Exit code is {}. Detail: {}",
-            path.as_ref().display(),
-            code,
-            String::from_utf8_lossy(&output.stdout)
-        );
-        return Err(Error::new(ErrorKind::Other, format!("exit code: {}", code)));
-    }
-
-    Ok(())
+/// Run `composer install` at the plugin directory.
+///
+/// Output will be saved to `log_dir` if given.
+/// Composer will be killed if it doesn't finish within `timeout`.
+pub async fn run_composer(
+    path: impl AsRef<Path>,
+    log_dir: Option<&Path>,
+    timeout: Option<Duration>,
+) -> Result<()> {
+    let path = path.as_ref();
+    info!("Running Composer at '{}'...", path.display());
+
+    let plugin = path.file_name().map(|name| name.to_string_lossy());
+    let mut command = Command::new("composer");
+    command.arg("install").arg("--no-dev").current_dir(path);
+    process::run(
+        &mut command,
+        "composer-install",
+        plugin.as_deref(),
+        log_dir,
+        timeout,
+    )
+    .await
 }
 
-async fn install_and_clean(lock: &ComposerPackages, path: impl AsRef<Path>) -> Result<()> {
-    let path_display = path.as_ref().display();
-    let composer_json = format!("{}/composer.json", path_display);
+async fn install(path: impl AsRef<Path>, log_dir: &Path, timeout: Option<Duration>) -> Result<()> {
+    let composer_json = format!("{}/composer.json", path.as_ref().display());
     if let Err(e) = fs::File::open(&composer_json).await {
         if e.kind() == ErrorKind::NotFound {
             // No composer.json, so there's no need to run Composer.
@@ -81,68 +70,46 @@ async fn install_and_clean(lock: &ComposerPackages, path: impl AsRef<Path>) -> R
         }
     }
 
-    run_composer(&path).await?;
-
-    dedupe(lock, &path, &path_display, &composer_json).await
+    run_composer(&path, Some(log_dir), timeout).await
 }
 
-pub async fn dedupe(
-    lock: &ComposerPackages,
-    path: impl AsRef<Path>,
-    display: &path::Display<'_>,
-    manifest_path: &str,
-) -> Result<()> {
-    let local_lock = parse_lock(&path).await?;
-    let vendor_path = format!("{display}/vendor");
-
-    let deletion = async {
-        let deletions = lock
-            .intersection(&local_lock)
-            .map(|name| vendor_path.clone() + "/" + name)
-            .map(fs::remove_dir_all);
-        try_join_all(deletions).await?;
-
-        let mut dirs = fs::read_dir(&vendor_path).await?;
-        while let Some(dir) = dirs.next_entry().await? {
-            let path = dir.path();
-            let items = ReadDirStream::new(fs::read_dir(&path).await?);
-            if items.count().await == 0 {
-                fs::remove_dir(&path).await?;
-            }
-        }
-
-        Ok(())
+/// Find Composer files of a plugin which won't be shipped,
+/// including packages which are already provided by Blessing Skin Server.
+///
+/// Returned paths are relative to the plugin directory. Nothing on disk is changed.
+pub async fn dedupe(lock: &ComposerPackages, path: impl AsRef<Path>) -> Result<Vec<String>> {
+    let mut excluded = vec![String::from("composer.json"), String::from("composer.lock")];
+
+    let local_lock = match parse_lock(&path).await {
+        Ok(local_lock) => local_lock,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(excluded),
+        Err(e) => return Err(e),
     };
+    let mut duplicated = lock
+        .intersection(&local_lock)
+        .map(|name| format!("vendor/{name}"))
+        .collect::<Vec<_>>();
+    duplicated.sort();
+    excluded.append(&mut duplicated);
 
-    let clean_up = try_join3(
-        deletion,
-        fs::remove_file(manifest_path),
-        fs::remove_file(format!("{display}/composer.lock")),
-    );
-    if clean_up.await.is_err() {
-        warn!("Failed to clean up Composer stuff at '{display}'");
-    }
-
-    Ok(())
+    Ok(excluded)
 }
 
 pub async fn install_php_dependencies<S: AsRef<str>>(
     path: impl AsRef<Path>,
     plugins: impl Iterator<Item = (S, S)>,
+    config: &ComposerConfig,
+    log_dir: &Path,
 ) -> Result<()> {
-    let bs_lock = fetch_bs_lock().await.unwrap_or_else(|e| {
-        warn!("Failed to fetch composer.lock of Blessing Skin Server: {e:?}");
-        HashSet::default()
-    });
-
     info!("Starting to install PHP dependencies...");
 
     let jobs = plugins
         .map(|(name, _)| {
             info!("Installing dependencies for plugin '{}'...", name.as_ref());
-            install_and_clean(
-                &bs_lock,
+            install(
                 format!("{}/plugins/{}", path.as_ref().display(), name.as_ref()),
+                log_dir,
+                config.timeout(),
             )
         })
         .collect::<Vec<_>>();
@@ -153,13 +120,23 @@ pub async fn install_php_dependencies<S: AsRef<str>>(
     Ok(())
 }
 
-async fn fetch_bs_lock() -> reqwest::Result<ComposerPackages> {
+/// Fetch packages of Blessing Skin Server, which won't be shipped with plugins.
+///
+/// Nothing will be deduplicated if it fails to fetch.
+pub async fn load_bs_lock(config: &ComposerConfig) -> ComposerPackages {
+    fetch_bs_lock(&config.bs_lock_url)
+        .await
+        .unwrap_or_else(|e| {
+            warn!("Failed to fetch composer.lock of Blessing Skin Server: {e:?}");
+            HashSet::default()
+        })
+}
+
+async fn fetch_bs_lock(url: &str) -> reqwest::Result<ComposerPackages> {
     let mut request = ClientBuilder::new()
         .user_agent("Rust reqwest/0.11")
         .build()?
-        .get(
-            "https://raw.githubusercontent.com/bs-community/blessing-skin-server/dev/composer.lock",
-        );
+        .get(url);
 
     if let Ok(token) = env::var("GITHUB_TOKEN") {
         request = request.header("Authorization", format!("Bearer {token}"));
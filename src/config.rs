@@ -0,0 +1,311 @@
+use serde::Deserialize;
+use std::{error::Error, fmt, io, path::Path, time::Duration};
+use tokio::fs;
+
+/// Configuration loaded from `nano.toml` at the root of plugins repository.
+///
+/// All fields are optional and fall back to the values used by official plugins repository.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Directory where zip files and registries are saved.
+    pub dist_dir: String,
+    /// Languages of registries. English is always required.
+    pub languages: Vec<String>,
+    pub registry: RegistryConfig,
+    pub composer: ComposerConfig,
+    pub build: BuildConfig,
+    pub zip: ZipConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RegistryConfig {
+    /// URL prefix of zip files listed in registries.
+    pub base_url: String,
+    /// Schema version of written registries.
+    /// `1` lists only the latest version of each package, for older Blessing Skin.
+    pub schema_version: u8,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ComposerConfig {
+    /// URL of `composer.lock` of Blessing Skin Server,
+    /// whose packages won't be shipped with plugins.
+    pub bs_lock_url: String,
+    /// Seconds before `composer install` of a plugin is killed. `0` means no limit.
+    pub timeout: u64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BuildConfig {
+    pub mode: BuildMode,
+    /// Package manager to install and build plugins.
+    /// It's detected by lockfile at repository root if not specified.
+    pub package_manager: Option<PackageManager>,
+    /// Refuse to update lockfile when installing dependencies.
+    pub frozen_lockfile: bool,
+    /// Extensions of source files which won't be shipped with plugins.
+    pub source_extensions: Vec<String>,
+    /// Seconds before `pnpm i` is killed. `0` means no limit.
+    pub install_timeout: u64,
+    /// Seconds before `pnpm build` is killed. `0` means no limit.
+    pub build_timeout: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BuildMode {
+    /// Install and build the whole repository at once.
+    Monorepo,
+    /// Install and build only changed plugins and their workspace dependencies.
+    PerPlugin,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ZipConfig {
+    pub symlinks: SymlinkPolicy,
+    /// Maximum size of a zip archive in MiB. `0` means no limit.
+    pub max_size: u64,
+    /// Maximum total size of extracted files of a zip archive in MiB. `0` means no limit.
+    pub max_unpacked_size: u64,
+}
+
+/// How symbolic links in plugins are packed into zip archives.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymlinkPolicy {
+    /// Pack the file or directory which the link points to.
+    #[default]
+    Follow,
+    /// Store the link itself. Its target must be a relative path inside the plugin.
+    Store,
+    /// Fail if there's any link.
+    Reject,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PackageManager {
+    Pnpm,
+    Yarn,
+    Npm,
+}
+
+impl PackageManager {
+    /// All package managers, in order of detection.
+    pub const ALL: [PackageManager; 3] = [
+        PackageManager::Pnpm,
+        PackageManager::Yarn,
+        PackageManager::Npm,
+    ];
+
+    pub fn program(self) -> &'static str {
+        match self {
+            PackageManager::Pnpm => "pnpm",
+            PackageManager::Yarn => "yarn",
+            PackageManager::Npm => "npm",
+        }
+    }
+
+    pub fn lockfile(self) -> &'static str {
+        match self {
+            PackageManager::Pnpm => "pnpm-lock.yaml",
+            PackageManager::Yarn => "yarn.lock",
+            PackageManager::Npm => "package-lock.json",
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            dist_dir: String::from(".dist"),
+            languages: vec![String::from("en"), String::from("zh_CN")],
+            registry: RegistryConfig::default(),
+            composer: ComposerConfig::default(),
+            build: BuildConfig::default(),
+            zip: ZipConfig::default(),
+        }
+    }
+}
+
+impl Default for RegistryConfig {
+    fn default() -> Self {
+        RegistryConfig {
+            base_url: String::from("https://bs-plugins.littleservice.cn/"),
+            schema_version: 1,
+        }
+    }
+}
+
+impl Default for ComposerConfig {
+    fn default() -> Self {
+        ComposerConfig {
+            bs_lock_url: String::from(
+                "https://raw.githubusercontent.com/bs-community/blessing-skin-server/dev/composer.lock",
+            ),
+            timeout: 600,
+        }
+    }
+}
+
+impl Default for BuildConfig {
+    fn default() -> Self {
+        BuildConfig {
+            mode: BuildMode::Monorepo,
+            package_manager: None,
+            frozen_lockfile: true,
+            source_extensions: vec![
+                String::from("ts"),
+                String::from("tsx"),
+                String::from("scss"),
+            ],
+            install_timeout: 600,
+            build_timeout: 900,
+        }
+    }
+}
+
+impl Default for ZipConfig {
+    fn default() -> Self {
+        ZipConfig {
+            symlinks: SymlinkPolicy::Follow,
+            max_size: 50,
+            max_unpacked_size: 200,
+        }
+    }
+}
+
+impl ComposerConfig {
+    pub fn timeout(&self) -> Option<Duration> {
+        seconds(self.timeout)
+    }
+}
+
+impl BuildConfig {
+    pub fn install_timeout(&self) -> Option<Duration> {
+        seconds(self.install_timeout)
+    }
+
+    pub fn build_timeout(&self) -> Option<Duration> {
+        seconds(self.build_timeout)
+    }
+}
+
+fn seconds(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Invalid { key: &'static str, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "failed to read 'nano.toml': {e}"),
+            ConfigError::Parse(e) => write!(f, "failed to parse 'nano.toml': {e}"),
+            ConfigError::Invalid { key, message } => {
+                write!(f, "invalid value of '{key}' in 'nano.toml': {message}")
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Io(e) => Some(e),
+            ConfigError::Parse(e) => Some(e),
+            ConfigError::Invalid { .. } => None,
+        }
+    }
+}
+
+impl Config {
+    /// Load `nano.toml` from root of plugins repository.
+    ///
+    /// Default configuration is used if that file doesn't exist.
+    pub async fn load(root: impl AsRef<Path>) -> Result<Config, ConfigError> {
+        let path = format!("{}/nano.toml", root.as_ref().display());
+        match fs::read_to_string(&path).await {
+            Ok(content) => {
+                info!("Loading configuration from '{path}'...");
+                Config::parse(&content)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(ConfigError::Io(e)),
+        }
+    }
+
+    pub fn parse(content: &str) -> Result<Config, ConfigError> {
+        let config = toml::from_str::<Config>(content).map_err(ConfigError::Parse)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.dist_dir.is_empty() {
+            return Err(invalid("dist_dir", "must not be empty"));
+        }
+        if !self.languages.iter().any(|lang| lang == "en") {
+            return Err(invalid("languages", "must contain \"en\""));
+        }
+        if let Some(lang) = self.languages.iter().find(|lang| !is_language(lang)) {
+            return Err(invalid(
+                "languages",
+                format!("\"{lang}\" isn't a valid language name"),
+            ));
+        }
+        if !is_http_url(&self.registry.base_url) {
+            return Err(invalid("registry.base_url", "must be an HTTP(S) URL"));
+        }
+        if !self.registry.base_url.ends_with('/') {
+            return Err(invalid("registry.base_url", "must end with \"/\""));
+        }
+        if !matches!(self.registry.schema_version, 1 | 2) {
+            return Err(invalid("registry.schema_version", "must be 1 or 2"));
+        }
+        if !is_http_url(&self.composer.bs_lock_url) {
+            return Err(invalid("composer.bs_lock_url", "must be an HTTP(S) URL"));
+        }
+        if let Some(ext) = self
+            .build
+            .source_extensions
+            .iter()
+            .find(|ext| ext.is_empty() || ext.starts_with('.'))
+        {
+            return Err(invalid(
+                "build.source_extensions",
+                format!("\"{ext}\" must be non-empty and without leading dot"),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+fn invalid(key: &'static str, message: impl Into<String>) -> ConfigError {
+    ConfigError::Invalid {
+        key,
+        message: message.into(),
+    }
+}
+
+fn is_language(lang: &str) -> bool {
+    !lang.is_empty()
+        && lang
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn is_http_url(url: &str) -> bool {
+    url.starts_with("https://") || url.starts_with("http://")
+}
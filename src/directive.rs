@@ -0,0 +1,127 @@
+use regex::Regex;
+use std::collections::BTreeSet;
+
+/// Special actions requested in commit messages.
+///
+/// Supported directives are:
+///
+/// - `force update: a, b` forces plugins to be re-built even if their versions aren't changed.
+/// - `force update: *` forces all plugins to be re-built.
+/// - `skip build: a, b` suppresses builds of plugins even if their versions are changed.
+/// - `[nano skip]` makes the whole run a no-op.
+///
+/// They can be given as Git trailers as well, such as `Nano-Force-Update: a, b`,
+/// `Nano-Skip-Build: a` and `Nano-Skip: true`.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Directives {
+    pub force_update: BTreeSet<String>,
+    pub force_update_all: bool,
+    pub skip_build: BTreeSet<String>,
+    pub skip_run: bool,
+}
+
+impl Directives {
+    pub fn parse(message: &str) -> Directives {
+        let mut directives = Directives {
+            skip_run: message.contains("[nano skip]"),
+            ..Default::default()
+        };
+
+        let re_force_update =
+            Regex::new(r"force update:[ \t]*([\w*-]+(?:[ \t]*,[ \t]*[\w*-]+)*)").unwrap();
+        for caps in re_force_update.captures_iter(message) {
+            for name in split_names(&caps[1]) {
+                if name == "*" {
+                    directives.force_update_all = true;
+                } else {
+                    directives.force_update.insert(name.to_owned());
+                }
+            }
+        }
+
+        let re_skip_build =
+            Regex::new(r"skip build:[ \t]*([\w-]+(?:[ \t]*,[ \t]*[\w-]+)*)").unwrap();
+        for caps in re_skip_build.captures_iter(message) {
+            directives
+                .skip_build
+                .extend(split_names(&caps[1]).map(String::from));
+        }
+
+        if let Ok(trailers) = git2::message_trailers_strs(message) {
+            for (key, value) in trailers.iter() {
+                if key.eq_ignore_ascii_case("Nano-Force-Update") {
+                    for name in split_names(value) {
+                        if name == "*" {
+                            directives.force_update_all = true;
+                        } else {
+                            directives.force_update.insert(name.to_owned());
+                        }
+                    }
+                } else if key.eq_ignore_ascii_case("Nano-Skip-Build") {
+                    directives
+                        .skip_build
+                        .extend(split_names(value).map(String::from));
+                } else if key.eq_ignore_ascii_case("Nano-Skip") {
+                    directives.skip_run |=
+                        matches!(value.trim().to_ascii_lowercase().as_str(), "true" | "yes");
+                }
+            }
+        }
+
+        directives
+    }
+
+    /// Parse and combine directives of several commit messages.
+    ///
+    /// All directives are honored, since it's unknown which commits change versions.
+    pub fn parse_all<S: AsRef<str>>(messages: impl IntoIterator<Item = S>) -> Directives {
+        messages
+            .into_iter()
+            .fold(Directives::default(), |mut directives, message| {
+                directives.merge(Directives::parse(message.as_ref()));
+                directives
+            })
+    }
+
+    /// Parse and combine directives of commits in a range, given newest first,
+    /// each with plugins whose versions were changed by that commit.
+    ///
+    /// `force update` directives of any commit are honored.
+    /// `skip build` of a plugin is dropped if a later commit changes its version again,
+    /// and `[nano skip]` is dropped if a commit without it changes any version or forces updates,
+    /// so a skipped commit doesn't suppress changes made by other commits.
+    pub fn parse_commits<'a, S, I>(commits: I) -> Directives
+    where
+        S: AsRef<str>,
+        I: IntoIterator<Item = (S, &'a BTreeSet<String>)>,
+        I::IntoIter: DoubleEndedIterator,
+    {
+        let mut combined = Directives::default();
+        let mut released = false;
+        for (message, bumped) in commits.into_iter().rev() {
+            let directives = Directives::parse(message.as_ref());
+            combined.skip_build.retain(|name| !bumped.contains(name));
+            released |= !directives.skip_run
+                && (!bumped.is_empty()
+                    || directives.force_update_all
+                    || !directives.force_update.is_empty());
+            combined.merge(directives);
+        }
+        combined.skip_run &= !released;
+        combined
+    }
+
+    pub fn merge(&mut self, other: Directives) {
+        self.force_update.extend(other.force_update);
+        self.force_update_all |= other.force_update_all;
+        self.skip_build.extend(other.skip_build);
+        self.skip_run |= other.skip_run;
+    }
+}
+
+fn split_names(names: &str) -> impl Iterator<Item = &str> {
+    names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+}
@@ -1,5 +1,8 @@
 use crate::types;
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::Path,
+};
 use tokio::fs;
 use yaml_rust::{ScanError, YamlLoader};
 
@@ -13,16 +16,46 @@ pub struct PluginInfo {
     pub description: Text,
 }
 
+/// Translations of a text, keyed by language.
+///
+/// English is always present, and it's used for languages without translation.
 #[derive(Clone)]
-pub struct Text {
-    pub en: String,
-    pub zh_cn: String,
+pub struct Text(BTreeMap<String, String>);
+
+impl Text {
+    /// Translation in the given language, falling back to English.
+    pub fn get(&self, lang: &str) -> &str {
+        self.0
+            .get(lang)
+            .or_else(|| self.0.get("en"))
+            .map(String::as_str)
+            .unwrap_or_default()
+    }
+
+    /// Languages which have translation.
+    pub fn languages(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+
+    async fn translate(path: &str, key: &str, languages: &BTreeSet<String>) -> Text {
+        let mut translations = BTreeMap::new();
+        translations.insert(String::from("en"), trans(path, key, "en").await);
+        for lang in languages.iter().filter(|lang| *lang != "en") {
+            if let Some(text) = lookup(path, key, lang).await {
+                translations.insert(lang.clone(), text);
+            }
+        }
+        Text(translations)
+    }
 }
 
 impl I18nStore {
+    /// Load titles and descriptions of plugins in the given languages,
+    /// as well as languages found in `lang` directory of each plugin.
     pub async fn create<S: AsRef<str>>(
         root: impl AsRef<Path>,
         plugins: impl Iterator<Item = S>,
+        languages: &[String],
     ) -> I18nStore {
         let root = root.as_ref().display();
 
@@ -45,14 +78,10 @@ impl I18nStore {
                 }
             };
 
-            let title = Text {
-                en: trans(&path, &package_json.title, "en").await,
-                zh_cn: trans(&path, &package_json.title, "zh_CN").await,
-            };
-            let description = Text {
-                en: trans(&path, &package_json.description, "en").await,
-                zh_cn: trans(&path, &package_json.description, "zh_CN").await,
-            };
+            let mut languages = languages.iter().cloned().collect::<BTreeSet<_>>();
+            languages.extend(discover_languages(&path).await);
+            let title = Text::translate(&path, &package_json.title, &languages).await;
+            let description = Text::translate(&path, &package_json.description, &languages).await;
             let plugin_info = PluginInfo { title, description };
             store.insert(plugin.to_string(), plugin_info);
         }
@@ -65,12 +94,34 @@ impl I18nStore {
     }
 }
 
-pub async fn trans(path: impl AsRef<Path>, key: &str, lang: &'static str) -> String {
+/// Find languages which have a directory under `lang` directory of a plugin.
+async fn discover_languages(path: &str) -> Vec<String> {
+    let mut languages = vec![];
+    let mut entries = match fs::read_dir(format!("{path}/lang")).await {
+        Ok(entries) => entries,
+        Err(_) => return languages,
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        if entry.file_type().await.map(|t| t.is_dir()).unwrap_or(false) {
+            languages.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    languages
+}
+
+pub async fn trans(path: impl AsRef<Path>, key: &str, lang: &str) -> String {
+    match lookup(path, key, lang).await {
+        Some(text) => text,
+        None => key.split("::").last().unwrap_or(key).to_owned(),
+    }
+}
+
+async fn lookup(path: impl AsRef<Path>, key: &str, lang: &str) -> Option<String> {
     let key = match key.split("::").last() {
         Some(key) => key,
         None => {
             warn!("I18n key '{key}' is incorrect. Translation will fail.");
-            return key.to_owned();
+            return None;
         }
     };
     let mut components = key.split('.');
@@ -80,22 +131,64 @@ pub async fn trans(path: impl AsRef<Path>, key: &str, lang: &'static str) -> Str
         components.next().unwrap_or_default()
     );
 
-    let content = match fs::read_to_string(&path).await {
-        Ok(content) => content,
-        Err(_) => return key.to_owned(),
-    };
+    let content = fs::read_to_string(&path).await.ok()?;
 
     extract(&content, components)
-        .unwrap_or_else(|_| {
-            warn!("Failed to parse YAML file: {path}");
-            Some(key.to_owned())
-        })
-        .unwrap_or_else(|| {
+        .map_err(|_| warn!("Failed to parse YAML file: {path}"))
+        .ok()?
+        .or_else(|| {
             warn!("Cannot find translation of key '{key}'.");
-            key.to_owned()
+            None
         })
 }
 
+/// Find i18n keys in titles and descriptions of plugins which can't be translated.
+///
+/// Only namespaced keys (like `plugin::general.title`) are checked,
+/// since other texts aren't meant to be translated.
+/// Each missing translation is reported as a human-readable message.
+pub async fn check<S: AsRef<str>>(
+    root: impl AsRef<Path>,
+    plugins: impl Iterator<Item = S>,
+    languages: &[String],
+) -> Vec<String> {
+    let root = root.as_ref().display();
+
+    let mut missing = vec![];
+    for plugin in plugins {
+        let plugin = plugin.as_ref();
+        let path = format!("{root}/plugins/{plugin}");
+        let package_json = match fs::read(format!("{path}/package.json")).await {
+            Ok(bytes) => match serde_json::from_slice::<types::PackageJson>(&bytes) {
+                Ok(manifest) => manifest,
+                Err(_) => {
+                    missing.push(format!("Failed to parse 'package.json' of '{plugin}'."));
+                    continue;
+                }
+            },
+            Err(_) => {
+                missing.push(format!("Failed to open file 'package.json' of '{plugin}'."));
+                continue;
+            }
+        };
+
+        for key in [&package_json.title, &package_json.description] {
+            if !key.contains("::") {
+                continue;
+            }
+            for lang in languages {
+                if lookup(&path, key, lang).await.is_none() {
+                    missing.push(format!(
+                        "Translation of '{key}' in '{lang}' is missing for plugin '{plugin}'."
+                    ));
+                }
+            }
+        }
+    }
+
+    missing
+}
+
 fn extract<'a>(
     content: &str,
     components: impl Iterator<Item = &'a str>,
@@ -0,0 +1,76 @@
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::{
+    io::{Error, ErrorKind, Result},
+    path::{Path, PathBuf},
+};
+
+/// Name of file which contains ignore rules of a plugin.
+pub const FILE_NAME: &str = ".nanoignore";
+
+/// Files which are never shipped with plugins.
+const DEFAULTS: &[&str] = &[
+    ".git/",
+    ".github/",
+    ".gitignore",
+    FILE_NAME,
+    "node_modules/",
+    "/tests/",
+    "/__tests__/",
+    "*.map",
+];
+
+/// Rules deciding which files of a plugin won't be shipped in zip archive.
+///
+/// They're built from defaults, source extensions in config,
+/// and `.nanoignore` file at plugin directory with gitignore syntax.
+/// Rules in `.nanoignore` take precedence, so defaults can be overridden with `!`.
+pub struct IgnoreRules {
+    matcher: Gitignore,
+    /// Paths excluded regardless of rules, such as deduplicated Composer packages.
+    excluded: Vec<PathBuf>,
+}
+
+impl IgnoreRules {
+    pub fn load(dir: impl AsRef<Path>, source_extensions: &[String]) -> Result<IgnoreRules> {
+        let dir = dir.as_ref();
+        let mut builder = GitignoreBuilder::new(dir);
+        let defaults = DEFAULTS
+            .iter()
+            .map(|glob| glob.to_string())
+            .chain(source_extensions.iter().map(|ext| format!("*.{ext}")));
+        for glob in defaults {
+            builder.add_line(None, &glob).map_err(invalid_rule)?;
+        }
+
+        let path = dir.join(FILE_NAME);
+        if path.is_file() {
+            if let Some(e) = builder.add(&path) {
+                return Err(invalid_rule(e));
+            }
+        }
+
+        let matcher = builder.build().map_err(invalid_rule)?;
+        Ok(IgnoreRules {
+            matcher,
+            excluded: vec![],
+        })
+    }
+
+    /// Exclude a path relative to plugin directory, with everything under it.
+    pub fn exclude(&mut self, path: impl Into<PathBuf>) {
+        self.excluded.push(path.into());
+    }
+
+    /// Check if a path relative to plugin directory is ignored.
+    pub fn is_ignored(&self, path: impl AsRef<Path>, is_dir: bool) -> bool {
+        let path = path.as_ref();
+        self.excluded
+            .iter()
+            .any(|excluded| path.starts_with(excluded))
+            || self.matcher.matched(path, is_dir).is_ignore()
+    }
+}
+
+fn invalid_rule(e: ignore::Error) -> Error {
+    Error::new(ErrorKind::InvalidData, e)
+}
@@ -1,9 +1,15 @@
 pub mod analyzer;
 pub mod build;
 pub mod composer;
+pub mod config;
+pub mod directive;
 pub mod i18n;
+pub mod ignore_rules;
+pub mod plan;
+pub mod process;
 pub mod registry;
 mod types;
+pub mod version;
 pub mod zip;
 
 #[macro_use]
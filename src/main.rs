@@ -1,15 +1,117 @@
-use futures::try_join;
+use clap::{Args, Parser, Subcommand};
+use futures::{stream, try_join, StreamExt, TryStreamExt};
 use nano::{
-    analyzer, build::build, composer::install_php_dependencies, i18n::I18nStore, registry,
-    zip::create_zip,
+    analyzer::{self, ChangeSet},
+    build::build,
+    composer::{self, install_php_dependencies, ComposerPackages},
+    config::Config,
+    i18n::{self, I18nStore},
+    ignore_rules::IgnoreRules,
+    plan::Plan,
+    process,
+    registry::{self, RegistryError},
+    version,
+    zip::{self, create_zip, Archive, ArchiveOptions},
 };
 use serde::Serialize;
-use std::env;
-use tokio::fs;
+use std::{
+    collections::BTreeMap,
+    env, iter,
+    path::{Path, PathBuf},
+    thread,
+};
+use tokio::{fs, signal, task};
 
 #[macro_use]
 extern crate log;
 
+/// Blessing Skin Plugins Marketplace Builder.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// Root directory of plugins repository.
+    #[arg(long, env = "PLUGINS_DIR", default_value = ".", global = true)]
+    plugins_dir: String,
+
+    /// Directory where zip files and registries are saved. Overrides `dist_dir` in `nano.toml`.
+    #[arg(long, global = true)]
+    dist_dir: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Build updated plugins and update registries.
+    Release(ReleaseArgs),
+    /// Print plugins which would be released.
+    Analyze(AnalyzeArgs),
+    /// Build a single plugin and install its PHP dependencies.
+    Build { plugin: String },
+    /// Pack a single plugin into zip file in the dist directory.
+    Zip { plugin: String },
+    /// Registry maintenance.
+    #[command(subcommand)]
+    Registry(RegistryCommand),
+    /// I18n maintenance.
+    #[command(subcommand)]
+    I18n(I18nCommand),
+}
+
+#[derive(Args)]
+struct AnalyzeArgs {
+    /// Revision of previous build. Only the latest commit is analyzed if omitted or empty.
+    #[arg(long, env = "BASE_REF")]
+    base: Option<String>,
+}
+
+#[derive(Args)]
+struct ReleaseArgs {
+    #[command(flatten)]
+    analyze: AnalyzeArgs,
+
+    /// Print what would be released without building anything.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Path to save the list of updated plugins.
+    ///
+    /// Titles there are in the first of `languages` in `nano.toml` other than English,
+    /// or English if there's no other language.
+    #[arg(long, default_value = "updated.json")]
+    updated_file: PathBuf,
+}
+
+#[derive(Subcommand)]
+enum RegistryCommand {
+    /// Check that registries agree with each other and with zip files in the dist directory.
+    ///
+    /// Zip files which aren't referenced by any registry are reported as warnings.
+    Verify,
+    /// Regenerate registries from all plugins in repository.
+    ///
+    /// Zip files in the dist directory are used to calculate digests,
+    /// and plugins without zip file are skipped.
+    /// Current versions which have been listed are regenerated, keeping their release dates.
+    /// Missing registries are created.
+    Rebuild,
+    /// Restore registries from the latest backup.
+    ///
+    /// The backup is removed after being restored,
+    /// so running it again goes further back.
+    Rollback,
+}
+
+#[derive(Subcommand)]
+enum I18nCommand {
+    /// Check that titles and descriptions of plugins can be translated.
+    Check {
+        /// Plugins to check. All plugins will be checked if omitted.
+        plugins: Vec<String>,
+    },
+}
+
 #[derive(Serialize)]
 struct UpdateInfo<'a> {
     name: &'a str,
@@ -19,53 +121,292 @@ struct UpdateInfo<'a> {
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     pretty_env_logger::init();
+    let mut cli = Cli::parse();
+    if cli.command.is_none() {
+        // Parse again as `release`, so its options are read from environment variables as well.
+        cli = Cli::parse_from(env::args_os().chain(iter::once("release".into())));
+    }
+
+    task::spawn(async {
+        if signal::ctrl_c().await.is_ok() {
+            error!("Interrupted. Stopping running steps...");
+            process::kill_all();
+            std::process::exit(130);
+        }
+    });
     println!("Blessing Skin Plugins Marketplace Builder.");
 
-    let path = env::var("PLUGINS_DIR").unwrap_or_else(|_| String::from("."));
+    let path = &cli.plugins_dir;
+    let mut config = Config::load(path).await?;
+    if let Some(dist) = cli.dist_dir {
+        config.dist_dir = dist;
+    }
+    let config = &config;
+    let dist = &config.dist_dir;
+    let log_dir = Path::new(dist).join("logs");
 
-    let (message, mut plugins) = analyzer::analyze(&path)?;
-    analyzer::analyze_commit_message(&message, &path, &mut plugins).await?;
-    if plugins.is_empty() {
-        return Ok(());
+    match cli.command.expect("`release` should be parsed by default") {
+        Command::Release(args) => release(path, config, args).await,
+        Command::Analyze(args) => analyze(path, config, args).await,
+        Command::Build { plugin } => {
+            let version = analyzer::plugin_version(path, &plugin).await?;
+            let plugins = || iter::once((plugin.as_str(), version.as_str()));
+            try_join!(
+                build(path, plugins(), &config.build, &log_dir),
+                install_php_dependencies(path, plugins(), &config.composer, &log_dir),
+            )?;
+            Ok(())
+        }
+        Command::Zip { plugin } => {
+            let version = analyzer::plugin_version(path, &plugin).await?;
+            fs::create_dir_all(dist).await?;
+            let bs_lock = composer::load_bs_lock(&config.composer).await;
+            let options = ArchiveOptions::new(zip::source_date_epoch(path)?);
+            zip_plugin(path, &plugin, &version, config, &bs_lock, &options).await?;
+            Ok(())
+        }
+        Command::Registry(RegistryCommand::Verify) => {
+            let errors = registry::verify(dist, &config.languages).await?;
+            let mut failed = 0;
+            for error in &errors {
+                if matches!(error, RegistryError::Orphaned { .. }) {
+                    warn!("{error}");
+                } else {
+                    error!("{error}");
+                    failed += 1;
+                }
+            }
+            if failed > 0 {
+                anyhow::bail!("Registry check found {failed} problem(s).");
+            }
+            Ok(())
+        }
+        Command::Registry(RegistryCommand::Rebuild) => rebuild_registry(path, config).await,
+        Command::Registry(RegistryCommand::Rollback) => {
+            let backup = registry::rollback(dist, &config.languages).await?;
+            info!("Restored registries from backup '{backup}'.");
+            Ok(())
+        }
+        Command::I18n(I18nCommand::Check { plugins }) => {
+            let plugins = if plugins.is_empty() {
+                analyzer::list_plugins(path).await?.into_iter().collect()
+            } else {
+                plugins
+            };
+            let missing = i18n::check(path, plugins.iter(), &config.languages).await;
+            for message in &missing {
+                error!("{message}");
+            }
+            if !missing.is_empty() {
+                anyhow::bail!("{} translation(s) are missing.", missing.len());
+            }
+            Ok(())
+        }
     }
+}
 
-    let i18n_store = I18nStore::create(&path, plugins.keys()).await;
+async fn analyze(path: &str, config: &Config, args: AnalyzeArgs) -> anyhow::Result<()> {
+    let (commits, mut changes) = analyzer::analyze(path, args.base.as_deref())?;
+    analyzer::analyze_commits(&commits, path, &mut changes).await?;
 
-    try_join!(
-        build(&path, plugins.iter()),
-        install_php_dependencies(&path, plugins.iter()),
-    )?;
+    let published = registry::published_versions(&config.dist_dir).await?;
+    let i18n_store = I18nStore::create(
+        path,
+        changes.to_build().map(|(name, _)| name),
+        &config.languages,
+    )
+    .await;
+    print!(
+        "{}",
+        Plan::new(&changes, &published, &i18n_store, &config.languages)
+    );
+
+    Ok(())
+}
 
-    for (name, version) in &plugins {
-        create_zip(
-            format!("{path}/plugins/{name}"),
-            format!(".dist/{name}_{version}.zip"),
-        )?;
+async fn release(path: &str, config: &Config, args: ReleaseArgs) -> anyhow::Result<()> {
+    let dry_run = args.dry_run;
+    let dist = &config.dist_dir;
+
+    let (commits, mut changes) = analyzer::analyze(path, args.analyze.base.as_deref())?;
+    analyzer::analyze_commits(&commits, path, &mut changes).await?;
+    if changes.is_empty() {
+        if dry_run {
+            println!("Nothing to release.");
+        }
+        return Ok(());
+    }
+
+    let published = registry::published_versions(dist).await?;
+    let i18n_store = I18nStore::create(
+        path,
+        changes.to_build().map(|(name, _)| name),
+        &config.languages,
+    )
+    .await;
+    if dry_run {
+        print!(
+            "{}",
+            Plan::new(&changes, &published, &i18n_store, &config.languages)
+        );
+    }
+
+    let errors = version::validate(&changes, &published);
+    if !errors.is_empty() {
+        for error in &errors {
+            error!("{error}");
+        }
+        anyhow::bail!("Version check failed for {} plugin(s).", errors.len());
+    }
+    if dry_run {
+        return Ok(());
     }
 
-    registry::operate_registry(".dist", &path, &plugins, &i18n_store).await?;
+    fs::create_dir_all(dist).await?;
+    let epoch = zip::source_date_epoch(path)?;
+    let archives = if changes.to_build().next().is_some() {
+        build_and_pack(path, config, &changes, epoch).await?
+    } else {
+        info!("No plugins need to be built, only registries will be updated.");
+        BTreeMap::new()
+    };
+
+    registry::operate_registry(dist, path, &changes, &archives, epoch, &i18n_store, config).await?;
 
     save_updated(
-        plugins.iter().map(|(k, v)| (k.as_str(), v.as_str())),
+        changes.to_build().map(|(k, v)| (k.as_str(), v.as_str())),
         &i18n_store,
+        config
+            .languages
+            .iter()
+            .find(|lang| *lang != "en")
+            .map_or("en", String::as_str),
+        &args.updated_file,
     )
     .await;
 
     Ok(())
 }
 
-async fn save_updated(plugins: impl Iterator<Item = (&str, &str)>, i18n_store: &I18nStore) {
+/// Build plugins in the change set, install their PHP dependencies and pack them.
+async fn build_and_pack(
+    path: &str,
+    config: &Config,
+    changes: &ChangeSet,
+    epoch: i64,
+) -> anyhow::Result<BTreeMap<String, Archive>> {
+    let log_dir = Path::new(&config.dist_dir).join("logs");
+
+    try_join!(
+        build(path, changes.to_build(), &config.build, &log_dir),
+        install_php_dependencies(path, changes.to_build(), &config.composer, &log_dir),
+    )?;
+
+    let bs_lock = composer::load_bs_lock(&config.composer).await;
+    let options = ArchiveOptions::new(epoch);
+    // Limit the number of archives being written at the same time.
+    // If one of them fails, those already started are still finished in background,
+    // but their zip files won't be referenced by registries.
+    let concurrency = thread::available_parallelism().map_or(1, usize::from);
+    let archives = stream::iter(changes.to_build())
+        .map(|(name, version)| zip_plugin(path, name, version, config, &bs_lock, &options))
+        .buffered(concurrency)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    Ok(changes
+        .to_build()
+        .map(|(name, _)| name.clone())
+        .zip(archives)
+        .collect())
+}
+
+async fn rebuild_registry(path: &str, config: &Config) -> anyhow::Result<()> {
+    let dist = &config.dist_dir;
+
+    let mut plugins = BTreeMap::new();
+    let mut archives = BTreeMap::new();
+    for name in analyzer::list_plugins(path).await? {
+        let version = analyzer::plugin_version(path, &name).await?;
+        match Archive::read(format!("{dist}/{}", zip::file_name(&name, &version))) {
+            Ok(archive) => {
+                archives.insert(name.clone(), archive);
+            }
+            Err(e) => warn!(
+                "Skipped plugin '{name}' since its zip file at version {version} can't be read: {e}"
+            ),
+        }
+        plugins.insert(name, version);
+    }
+
+    let i18n_store = I18nStore::create(path, plugins.keys(), &config.languages).await;
+    let epoch = zip::source_date_epoch(path)?;
+    fs::create_dir_all(dist).await?;
+    registry::rebuild_registry(dist, path, &plugins, &archives, epoch, &i18n_store, config).await?;
+
+    Ok(())
+}
+
+/// Pack files of a plugin which will be shipped into the dist directory.
+///
+/// Packing and checking run on the blocking thread pool,
+/// so archives of several plugins can be created concurrently.
+/// Archives are written to disk directly instead of being kept in memory.
+async fn zip_plugin(
+    path: &str,
+    name: &str,
+    version: &str,
+    config: &Config,
+    bs_lock: &ComposerPackages,
+    options: &ArchiveOptions,
+) -> anyhow::Result<Archive> {
+    let dir = format!("{path}/plugins/{name}");
+    let mut rules = IgnoreRules::load(&dir, &config.build.source_extensions)?;
+    for excluded in composer::dedupe(bs_lock, &dir).await? {
+        rules.exclude(excluded);
+    }
+
+    let dest = format!("{}/{}", config.dist_dir, zip::file_name(name, version));
+    let zip_config = config.zip.clone();
+    let options = *options;
+    let (name, version) = (name.to_owned(), version.to_owned());
+    task::spawn_blocking(move || {
+        let files = zip::stage(&dir, &rules, zip_config.symlinks)?;
+        let archive = create_zip(&files, &dest, &options)?;
+
+        let errors = zip::verify(&dest, &name, &version, &zip_config);
+        for error in &errors {
+            error!("{error}");
+        }
+        if !errors.is_empty() {
+            anyhow::bail!("Archive check failed for plugin '{name}'.");
+        }
+        info!(
+            "Packed plugin '{name}' ({} bytes, SHA-256 {}).",
+            archive.size, archive.sha256
+        );
+        Ok(archive)
+    })
+    .await?
+}
+
+async fn save_updated(
+    plugins: impl Iterator<Item = (&str, &str)>,
+    i18n_store: &I18nStore,
+    lang: &str,
+    path: impl AsRef<Path>,
+) {
     let updated = plugins
         .map(|(name, version)| UpdateInfo {
             name: i18n_store
                 .get(name.as_ref())
-                .map(|info| info.title.zh_cn.as_str())
+                .map(|info| info.title.get(lang))
                 .unwrap_or_default(),
             version,
         })
         .collect::<Vec<_>>();
     if let Ok(bytes) = serde_json::to_vec(&updated) {
-        if fs::write("updated.json", &bytes).await.is_err() {
+        if fs::write(path, &bytes).await.is_err() {
             warn!("Failed to save updated plugins list.");
         }
     }
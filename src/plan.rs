@@ -0,0 +1,108 @@
+use crate::{
+    analyzer::{Change, ChangeSet},
+    i18n::I18nStore,
+    zip,
+};
+use std::{collections::BTreeMap, fmt};
+
+/// What a release would do, without doing it.
+pub struct Plan {
+    entries: Vec<PlanEntry>,
+    languages: Vec<String>,
+}
+
+struct PlanEntry {
+    name: String,
+    title: Option<String>,
+    change: Change,
+    published: Option<String>,
+    forced: bool,
+}
+
+impl Plan {
+    pub fn new(
+        changes: &ChangeSet,
+        published: &BTreeMap<String, String>,
+        i18n_store: &I18nStore,
+        languages: &[String],
+    ) -> Plan {
+        let entries = changes
+            .iter()
+            .map(|(name, change)| {
+                let published_name = match change {
+                    Change::Renamed { from, .. } => from,
+                    _ => name,
+                };
+                PlanEntry {
+                    name: name.clone(),
+                    title: i18n_store
+                        .get(name)
+                        .map(|info| info.title.get("en").to_owned()),
+                    change: change.clone(),
+                    published: published.get(published_name).cloned(),
+                    forced: changes.is_forced(name),
+                }
+            })
+            .collect();
+
+        Plan {
+            entries,
+            languages: languages.to_vec(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "Nothing to release.");
+        }
+
+        writeln!(f, "Release plan:")?;
+        for entry in &self.entries {
+            let name = &entry.name;
+            let published = entry.published.as_deref().unwrap_or("(none)");
+            let title = entry
+                .title
+                .as_ref()
+                .map(|title| format!(" ({title})"))
+                .unwrap_or_default();
+            let forced = if entry.forced { " [forced]" } else { "" };
+
+            let action = match &entry.change {
+                Change::Added { version } => {
+                    writeln!(f, "  + {name}{title}: {published} -> {version}{forced}")?;
+                    format!("add '{name}'")
+                }
+                Change::Updated { version } => {
+                    writeln!(f, "  ~ {name}{title}: {published} -> {version}{forced}")?;
+                    format!("update '{name}'")
+                }
+                Change::Renamed { from, version } => {
+                    writeln!(
+                        f,
+                        "  > {from} -> {name}{title}: {published} -> {version}{forced}"
+                    )?;
+                    format!("remove '{from}', add '{name}'")
+                }
+                Change::Removed => {
+                    writeln!(f, "  - {name}: {published} -> (removed)")?;
+                    format!("remove '{name}'")
+                }
+            };
+
+            if let Some(version) = entry.change.version() {
+                writeln!(f, "      zip: {}", zip::file_name(name, version))?;
+            }
+            for lang in &self.languages {
+                writeln!(f, "      registry_{lang}.json: {action}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,261 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeSet,
+    error::Error,
+    fmt,
+    path::Path,
+    process::{ExitStatus, Stdio},
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+use tokio::{
+    fs,
+    io::{self, AsyncBufReadExt, AsyncRead, BufReader},
+    process::Command,
+    time,
+};
+
+/// Process groups of running commands, so they can be killed before exiting.
+static GROUPS: Mutex<BTreeSet<u32>> = Mutex::new(BTreeSet::new());
+
+/// Error of an external command which didn't finish successfully.
+///
+/// It's wrapped in [`std::io::Error`] when returned.
+#[derive(Debug)]
+pub struct StepError {
+    pub step: String,
+    pub plugin: Option<String>,
+    pub kind: StepErrorKind,
+}
+
+#[derive(Debug)]
+pub enum StepErrorKind {
+    /// The command exited unsuccessfully.
+    /// Full output of that command (both stdout and stderr) is attached.
+    Failed { code: Option<i32>, output: String },
+    /// The command didn't finish in time and was killed.
+    TimedOut { timeout: Duration, output: String },
+}
+
+impl fmt::Display for StepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "step '{}'", self.step)?;
+        if let Some(plugin) = &self.plugin {
+            write!(f, " of plugin '{plugin}'")?;
+        }
+        match &self.kind {
+            StepErrorKind::Failed { code, output } => {
+                match code {
+                    Some(code) => write!(f, " failed with exit code {code}")?,
+                    None => write!(f, " was terminated by signal")?,
+                }
+                write!(f, ". Output:\n{output}")
+            }
+            StepErrorKind::TimedOut { timeout, output } => write!(
+                f,
+                " timed out after {} seconds. Output:\n{output}",
+                timeout.as_secs()
+            ),
+        }
+    }
+}
+
+impl Error for StepError {}
+
+/// Run an external command as a step of building.
+///
+/// Both stdout and stderr are streamed to log with a prefix of plugin name and step,
+/// and saved to `<log_dir>/<plugin>.<step>.log` if `log_dir` is given.
+///
+/// The command and all its descendants are killed if it doesn't finish within `timeout`,
+/// or the returned future is dropped before the command exits.
+/// Since they're in their own process group, they don't receive Ctrl-C from terminal,
+/// so [`kill_all`] should be called before exiting on Ctrl-C.
+pub async fn run(
+    command: &mut Command,
+    step: &str,
+    plugin: Option<&str>,
+    log_dir: Option<&Path>,
+    timeout: Option<Duration>,
+) -> io::Result<()> {
+    let prefix = match plugin {
+        Some(plugin) => format!("{plugin}.{step}"),
+        None => step.to_owned(),
+    };
+
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    #[cfg(unix)]
+    // SAFETY: `setpgid` is async-signal-safe.
+    unsafe {
+        // Put the child in a new process group, so we can kill its descendants together.
+        command.pre_exec(|| {
+            if libc::setpgid(0, 0) == 0 {
+                Ok(())
+            } else {
+                Err(std::io::Error::last_os_error())
+            }
+        });
+    }
+    let mut child = command.spawn()?;
+    let mut group = ProcessGroup::new(child.id());
+    let stdout = child.stdout.take().expect("stdout should be piped");
+    let stderr = child.stderr.take().expect("stderr should be piped");
+
+    let output = RefCell::new(String::new());
+    let status = {
+        let wait = async {
+            tokio::try_join!(
+                child.wait(),
+                forward(stdout, &prefix, &output),
+                forward(stderr, &prefix, &output),
+            )
+        };
+        let deadline = async {
+            match timeout {
+                Some(timeout) => time::sleep(timeout).await,
+                None => futures::future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            result = wait => Some(result?.0),
+            _ = deadline => None,
+        }
+    };
+    let output = output.into_inner();
+
+    if let Some(log_dir) = log_dir {
+        save_log(log_dir, &prefix, &output).await;
+    }
+
+    if let Some(status) = status {
+        group.disarm();
+        return check_status(status, step, plugin, output);
+    }
+
+    error!("Step '{prefix}' timed out.");
+    drop(group);
+    if child.kill().await.is_err() {
+        warn!("Failed to kill process {:?}.", child.id());
+    }
+
+    Err(io::Error::other(StepError {
+        step: step.to_owned(),
+        plugin: plugin.map(String::from),
+        kind: StepErrorKind::TimedOut {
+            timeout: timeout.unwrap_or_default(),
+            output,
+        },
+    }))
+}
+
+/// Process group of a running command, which is killed when dropped
+/// unless the command has exited.
+struct ProcessGroup {
+    pid: Option<u32>,
+}
+
+impl ProcessGroup {
+    fn new(pid: Option<u32>) -> ProcessGroup {
+        if let Some(pid) = pid {
+            lock_groups().insert(pid);
+        }
+        ProcessGroup { pid }
+    }
+
+    /// Keep the process group alive after the command exited.
+    fn disarm(&mut self) {
+        if let Some(pid) = self.pid.take() {
+            lock_groups().remove(&pid);
+        }
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        if let Some(pid) = self.pid.take() {
+            lock_groups().remove(&pid);
+            kill_group(pid);
+        }
+    }
+}
+
+fn lock_groups() -> std::sync::MutexGuard<'static, BTreeSet<u32>> {
+    GROUPS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Kill all processes in the given process group.
+fn kill_group(pid: u32) {
+    #[cfg(unix)]
+    // SAFETY: it only sends a signal; negative pid means the whole process group.
+    unsafe {
+        libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+    }
+    #[cfg(not(unix))]
+    let _ = pid;
+}
+
+/// Kill all running commands and their descendants.
+///
+/// It should be called before exiting the process abruptly, such as on Ctrl-C.
+pub fn kill_all() {
+    for pid in lock_groups().iter() {
+        kill_group(*pid);
+    }
+}
+
+async fn forward(
+    stream: impl AsyncRead + Unpin,
+    prefix: &str,
+    output: &RefCell<String>,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut line = vec![];
+    while reader.read_until(b'\n', &mut line).await? > 0 {
+        let text = String::from_utf8_lossy(&line);
+        info!("[{prefix}] {}", text.trim_end());
+        output.borrow_mut().push_str(&text);
+        line.clear();
+    }
+
+    Ok(())
+}
+
+async fn save_log(log_dir: &Path, prefix: &str, output: &str) {
+    let path = log_dir.join(format!("{prefix}.log"));
+    let result = async {
+        fs::create_dir_all(log_dir).await?;
+        fs::write(&path, output).await
+    };
+    if result.await.is_err() {
+        warn!("Failed to save log to '{}'.", path.display());
+    }
+}
+
+fn check_status(
+    status: ExitStatus,
+    step: &str,
+    plugin: Option<&str>,
+    output: String,
+) -> io::Result<()> {
+    if status.success() {
+        return Ok(());
+    }
+
+    match plugin {
+        Some(plugin) => error!("Failed to run step '{step}' of plugin '{plugin}'."),
+        None => error!("Failed to run step '{step}'."),
+    }
+    Err(io::Error::other(StepError {
+        step: step.to_owned(),
+        plugin: plugin.map(String::from),
+        kind: StepErrorKind::Failed {
+            code: status.code(),
+            output,
+        },
+    }))
+}
@@ -1,20 +1,31 @@
-use crate::{i18n::I18nStore, types::PackageJson};
+use crate::{
+    analyzer::{Change, ChangeSet},
+    config::Config,
+    i18n::I18nStore,
+    types::PackageJson,
+    zip::{self, Archive},
+};
+use semver::Version;
 use serde::{Deserialize, Serialize};
-use sha2::{Digest, Sha256};
 use std::{
-    collections::{BTreeMap, HashMap},
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    io::{Error, ErrorKind},
     path::Path,
 };
+use time::{format_description::well_known::Rfc3339, macros::format_description, OffsetDateTime};
 use tokio::{fs, io::Result};
 
+/// Registry file of the first schema, which lists only the latest version of each package.
 #[derive(Serialize, Deserialize)]
-pub struct Registry {
+struct RegistryV1 {
     version: u8,
-    packages: Vec<Package>,
+    packages: Vec<PackageV1>,
 }
 
 #[derive(Serialize, Deserialize)]
-pub struct Package {
+struct PackageV1 {
     name: String,
     version: String,
     title: String,
@@ -24,21 +35,151 @@ pub struct Package {
     dist: Dist,
 }
 
+/// Registry file of the second schema, which lists all published versions of each package.
 #[derive(Serialize, Deserialize)]
-struct Dist {
-    r#type: String,
-    url: String,
-    shasum: String,
+struct RegistryV2 {
+    version: u8,
+    packages: Vec<Package>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Package {
+    pub name: String,
+    pub title: String,
+    pub description: String,
+    pub author: String,
+    /// Published versions, newest first.
+    pub versions: Vec<Release>,
 }
 
-fn to_map(list: Vec<Package>) -> BTreeMap<String, Package> {
-    list.into_iter()
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Release {
+    pub version: String,
+    pub require: BTreeMap<String, String>,
+    pub dist: Dist,
+    /// Release date in RFC 3339 format.
+    /// It's unknown for versions migrated from registries of the first schema.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub released_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Dist {
+    pub r#type: String,
+    pub url: String,
+    pub shasum: String,
+}
+
+impl Package {
+    /// The newest published version.
+    pub fn latest(&self) -> Option<&Release> {
+        self.versions.first()
+    }
+
+    /// Add a release, replacing the one with the same version if it exists.
+    fn publish(&mut self, release: Release) {
+        self.versions
+            .retain(|published| published.version != release.version);
+        self.versions.push(release);
+        self.versions
+            .sort_by(|a, b| compare_versions(&b.version, &a.version));
+    }
+}
+
+impl From<PackageV1> for Package {
+    fn from(package: PackageV1) -> Self {
+        Package {
+            name: package.name,
+            title: package.title,
+            description: package.description,
+            author: package.author,
+            versions: vec![Release {
+                version: package.version,
+                require: package.require,
+                dist: package.dist,
+                released_at: None,
+            }],
+        }
+    }
+}
+
+/// Compare versions by semver, falling back to string comparison for invalid ones.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    match (Version::parse(a), Version::parse(b)) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+/// Parse a registry file of any schema.
+///
+/// Registries of the first schema are migrated, with a single version for each package.
+pub fn parse_registry(json: &str) -> Result<BTreeMap<String, Package>> {
+    #[derive(Deserialize)]
+    struct Header {
+        version: u8,
+    }
+
+    let invalid = |e| Error::new(ErrorKind::InvalidData, e);
+    let packages = match serde_json::from_str::<Header>(json)
+        .map_err(invalid)?
+        .version
+    {
+        1 => serde_json::from_str::<RegistryV1>(json)
+            .map_err(invalid)?
+            .packages
+            .into_iter()
+            .map(Package::from)
+            .collect(),
+        2 => {
+            serde_json::from_str::<RegistryV2>(json)
+                .map_err(invalid)?
+                .packages
+        }
+        version => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unsupported registry schema version {version}"),
+            ))
+        }
+    };
+
+    Ok(packages
+        .into_iter()
         .map(|package| (package.name.clone(), package))
-        .collect()
+        .collect())
 }
 
-fn to_list(map: BTreeMap<String, Package>) -> Vec<Package> {
-    map.into_iter().map(|(_, package)| package).collect()
+/// Serialize packages to registry file of the given schema.
+///
+/// Only the latest version of each package is kept for the first schema.
+pub fn serialize_registry(packages: &BTreeMap<String, Package>, schema: u8) -> Vec<u8> {
+    let json = if schema == 1 {
+        let packages = packages
+            .values()
+            .filter_map(|package| {
+                package.latest().map(|release| PackageV1 {
+                    name: package.name.clone(),
+                    version: release.version.clone(),
+                    title: package.title.clone(),
+                    description: package.description.clone(),
+                    author: package.author.clone(),
+                    require: release.require.clone(),
+                    dist: release.dist.clone(),
+                })
+            })
+            .collect();
+        serde_json::to_vec_pretty(&RegistryV1 {
+            version: 1,
+            packages,
+        })
+    } else {
+        serde_json::to_vec_pretty(&RegistryV2 {
+            version: 2,
+            packages: packages.values().cloned().collect(),
+        })
+    };
+    json.expect("Failed to serialize registry to JSON.")
 }
 
 async fn read_registry(path: impl AsRef<Path>) -> Result<BTreeMap<String, Package>> {
@@ -48,138 +189,536 @@ async fn read_registry(path: impl AsRef<Path>) -> Result<BTreeMap<String, Packag
     );
 
     let json = fs::read_to_string(path).await?;
-    let registry = serde_json::from_str::<Registry>(&json)
-        .map_err(|e| {
-            error!("Failed to parse previous registry data.");
-            e
-        })
-        .expect("Failed to parse previous registry data.");
+    parse_registry(&json).inspect_err(|_| error!("Failed to parse previous registry data."))
+}
+
+/// Read registry, or start with an empty one if it doesn't exist.
+async fn read_or_create_registry(path: impl AsRef<Path>) -> Result<BTreeMap<String, Package>> {
+    match read_registry(&path).await {
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            info!(
+                "Registry '{}' doesn't exist, creating a new one.",
+                path.as_ref().display()
+            );
+            Ok(BTreeMap::new())
+        }
+        result => result,
+    }
+}
 
-    Ok(to_map(registry.packages))
+/// Get the latest version of each package which has been published in the registry.
+///
+/// An empty map is returned if there's no registry yet.
+pub async fn published_versions(path: &str) -> Result<BTreeMap<String, String>> {
+    match read_registry(format!("{path}/registry_en.json")).await {
+        Ok(packages) => Ok(packages
+            .into_iter()
+            .filter_map(|(name, package)| {
+                package
+                    .latest()
+                    .map(|release| (name, release.version.clone()))
+            })
+            .collect()),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e),
+    }
 }
 
-async fn update_registry<'a, S1, S2>(
+#[allow(clippy::too_many_arguments)]
+async fn update_registry<'a, S: AsRef<str>>(
     packages: &'a mut BTreeMap<String, Package>,
-    plugins_dir: S1,
-    updated: impl Iterator<Item = (S2, S2)>,
-    hashes: &'a HashMap<&'a str, String>,
-    lang: &'static str,
+    plugins_dir: S,
+    changes: &'a ChangeSet,
+    archives: &'a BTreeMap<String, Archive>,
+    lang: &str,
+    base_url: &str,
+    released_at: &str,
     i18n_store: &'a I18nStore,
-) -> Result<()>
-where
-    S1: AsRef<str>,
-    S2: AsRef<str>,
-{
+) -> Result<()> {
     info!("Updating registry data for language '{lang}'...");
 
-    for (name, version) in updated {
-        let name = name.as_ref();
-        let version = version.as_ref();
+    for (name, change) in changes.iter() {
+        let version = match change {
+            Change::Removed => {
+                info!("Removing package '{name}' from registry.");
+                packages.remove(name);
+                continue;
+            }
+            Change::Renamed { from, version } => {
+                info!("Removing package '{from}' from registry since it's renamed to '{name}'.");
+                packages.remove(from);
+                version
+            }
+            Change::Added { version } | Change::Updated { version } => version,
+        };
         let json = fs::read_to_string(format!(
             "{}/plugins/{name}/package.json",
             plugins_dir.as_ref(),
         ))
         .await?;
-        let package_json = serde_json::from_str::<PackageJson>(&json)
-            .map_err(|e| {
-                error!("Failed to parse 'package.json` of plugin '{name}'.");
-                e
-            })
-            .expect("Failed to parse 'package.json' file.");
+        let package_json = serde_json::from_str::<PackageJson>(&json).map_err(|e| {
+            error!("Failed to parse 'package.json' of plugin '{name}'.");
+            Error::new(ErrorKind::InvalidData, e)
+        })?;
 
-        let i18n = i18n_store
-            .get(name)
-            .or_else(|| {
-                error!("Cannot retrieve i18n texts of plugin {name}.");
-                None
-            })
-            .expect("Cannot retrieve i18n texts.");
-
-        packages.insert(
-            name.to_owned(),
-            Package {
-                name: package_json.name,
-                version: package_json.version,
-                title: match lang {
-                    "en" => i18n.title.en.clone(),
-                    "zh_CN" => i18n.title.zh_cn.clone(),
-                    _ => package_json.title,
-                },
-                description: match lang {
-                    "en" => i18n.description.en.clone(),
-                    "zh_CN" => i18n.description.zh_cn.clone(),
-                    _ => package_json.description,
-                },
-                author: package_json.author,
-                require: package_json.require,
-                dist: Dist {
-                    r#type: String::from("zip"),
-                    url: format!(
-                        "https://bs-plugins.littleservice.cn/{name}_{version}.zip",
-                    ),
-                    shasum: hashes.get(name).map(|s| s.to_owned()).unwrap_or_default(),
-                },
+        let archive = archives.get(name).ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("zip file of plugin '{name}' is missing"),
+            )
+        })?;
+        let i18n = i18n_store.get(name).ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                format!("cannot retrieve i18n texts of plugin '{name}'"),
+            )
+        })?;
+
+        let release = Release {
+            version: package_json.version,
+            require: package_json.require,
+            dist: Dist {
+                r#type: String::from("zip"),
+                url: format!("{base_url}{}", zip::file_name(name, version)),
+                shasum: archive.sha256.clone(),
             },
-        );
+            released_at: Some(released_at.to_owned()),
+        };
+
+        let package = packages.entry(name.to_owned()).or_insert_with(|| Package {
+            name: name.to_owned(),
+            title: String::new(),
+            description: String::new(),
+            author: String::new(),
+            versions: vec![],
+        });
+        package.name = package_json.name;
+        package.title = i18n.title.get(lang).to_owned();
+        package.description = i18n.description.get(lang).to_owned();
+        package.author = package_json.author;
+        package.publish(release);
     }
 
     Ok(())
 }
 
-async fn write_registry(path: impl AsRef<Path>, packages: BTreeMap<String, Package>) -> Result<()> {
-    info!("Saving registry data to '{}'...", path.as_ref().display());
+/// Directory in the dist directory where previous registries are kept.
+const BACKUP_DIR: &str = "backups";
+
+/// Write registries of all languages, keyed by language.
+///
+/// Registries are written to temporary files first,
+/// which are renamed into place only after all of them are written,
+/// so a failure never leaves a truncated registry or languages out of sync.
+/// Previous registries are copied into a timestamped backup directory before being replaced.
+async fn write_registries(
+    path: &str,
+    registries: &BTreeMap<&str, BTreeMap<String, Package>>,
+    schema: u8,
+) -> Result<()> {
+    let mut written = vec![];
+    for (lang, packages) in registries {
+        let temp = format!("{path}/registry_{lang}.json.tmp");
+        info!("Saving registry data to '{temp}'...");
+        let result = fs::write(&temp, serialize_registry(packages, schema)).await;
+        written.push(temp);
+        if let Err(e) = result {
+            error!("Failed to save registry of '{lang}', previous registries are kept.");
+            for temp in written {
+                let _ = fs::remove_file(temp).await;
+            }
+            return Err(e);
+        }
+    }
+
+    backup_registries(path, registries.keys()).await?;
 
-    let registry = Registry {
-        version: 1,
-        packages: to_list(packages),
+    for lang in registries.keys() {
+        let dest = format!("{path}/registry_{lang}.json");
+        fs::rename(format!("{dest}.tmp"), &dest).await?;
+    }
+
+    Ok(())
+}
+
+/// Copy current registries into a new backup directory named after current time.
+///
+/// Registries which don't exist yet are skipped.
+async fn backup_registries<'a>(
+    path: &str,
+    languages: impl Iterator<Item = &'a &'a str>,
+) -> Result<()> {
+    let format = format_description!("[year][month][day]T[hour][minute][second]Z");
+    let timestamp = OffsetDateTime::now_utc()
+        .format(format)
+        .map_err(Error::other)?;
+
+    fs::create_dir_all(format!("{path}/{BACKUP_DIR}")).await?;
+    let mut backup = format!("{path}/{BACKUP_DIR}/{timestamp}");
+    let mut suffix = 0;
+    loop {
+        match fs::create_dir(&backup).await {
+            Ok(_) => break,
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                suffix += 1;
+                backup = format!("{path}/{BACKUP_DIR}/{timestamp}-{suffix}");
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    for lang in languages {
+        let file = format!("registry_{lang}.json");
+        match fs::copy(format!("{path}/{file}"), format!("{backup}/{file}")).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    info!("Previous registries are kept in '{backup}'.");
+
+    Ok(())
+}
+
+/// Restore registries of all languages from the latest backup, and remove that backup.
+///
+/// Registries which didn't exist when the backup was made are removed.
+/// Name of the restored backup is returned.
+pub async fn rollback(path: &str, languages: &[String]) -> Result<String> {
+    let backups_dir = format!("{path}/{BACKUP_DIR}");
+    let mut backups = vec![];
+    let mut entries = match fs::read_dir(&backups_dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Err(Error::new(ErrorKind::NotFound, "no registry backup found"))
+        }
+        Err(e) => return Err(e),
+    };
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_dir() {
+            backups.push(entry.file_name().to_string_lossy().into_owned());
+        }
+    }
+    let name = backups
+        .into_iter()
+        .max_by(|a, b| compare_backups(a, b))
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "no registry backup found"))?;
+    let backup = format!("{backups_dir}/{name}");
+    info!("Restoring registries from '{backup}'...");
+
+    let mut restored = vec![];
+    for lang in languages {
+        let file = format!("registry_{lang}.json");
+        let temp = format!("{path}/{file}.tmp");
+        match fs::copy(format!("{backup}/{file}"), &temp).await {
+            Ok(_) => restored.push((Some(temp), format!("{path}/{file}"))),
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                restored.push((None, format!("{path}/{file}")))
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    for (temp, dest) in restored {
+        match temp {
+            Some(temp) => fs::rename(temp, dest).await?,
+            None => match fs::remove_file(&dest).await {
+                Ok(_) => info!("Removing '{dest}' since it didn't exist in backup."),
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            },
+        }
+    }
+
+    fs::remove_dir_all(&backup).await?;
+    Ok(name)
+}
+
+/// Order backup names by time, then by the suffix added for backups made in the same second.
+fn compare_backups(a: &str, b: &str) -> Ordering {
+    let split = |name: &str| match name.split_once('-') {
+        Some((timestamp, suffix)) => (timestamp.to_owned(), suffix.parse().unwrap_or(0)),
+        None => (name.to_owned(), 0u32),
     };
-    let json = serde_json::to_vec_pretty(&registry).expect("Failed to serialize registry to JSON.");
+    split(a).cmp(&split(b))
+}
 
-    fs::write(path, &json).await
+/// Problem found when checking registries against each other and against zip files.
+#[derive(Debug)]
+pub enum RegistryError {
+    /// A release isn't listed in registries of some languages.
+    MissingRelease {
+        name: String,
+        version: String,
+        languages: Vec<String>,
+    },
+    /// The zip file which a release points to doesn't exist in the dist directory.
+    MissingArchive {
+        name: String,
+        version: String,
+        file: String,
+    },
+    /// The digest of a release doesn't match its zip file.
+    DigestMismatch {
+        name: String,
+        version: String,
+        expected: String,
+        found: String,
+    },
+    /// A zip file in the dist directory isn't referenced by any registry.
+    Orphaned { file: String },
 }
 
-fn calculate_hashes<'a>(
-    path: &'a str,
-    updated_plugins: &'a HashMap<String, String>,
-) -> HashMap<&'a str, String> {
-    info!("Calculating SHA256 hash of zip files...");
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::MissingRelease {
+                name,
+                version,
+                languages,
+            } => write!(
+                f,
+                "version '{version}' of package '{name}' is missing in registry of {}",
+                languages.join(", ")
+            ),
+            RegistryError::MissingArchive {
+                name,
+                version,
+                file,
+            } => write!(
+                f,
+                "zip file '{file}' of package '{name}' at version '{version}' doesn't exist"
+            ),
+            RegistryError::DigestMismatch {
+                name,
+                version,
+                expected,
+                found,
+            } => write!(
+                f,
+                "package '{name}' at version '{version}' has digest '{expected}' in registry, \
+                but its zip file has '{found}'"
+            ),
+            RegistryError::Orphaned { file } => {
+                write!(f, "zip file '{file}' isn't referenced by any registry")
+            }
+        }
+    }
+}
 
-    updated_plugins
-        .iter()
-        .map(|(name, version)| -> std::io::Result<_> {
-            let mut file = std::fs::File::open(format!("{path}/{name}_{version}.zip"))?;
-            let mut hasher = Sha256::new();
-            std::io::copy(&mut file, &mut hasher)?;
+impl std::error::Error for RegistryError {}
 
-            let hash = hasher.finalize();
-            Ok((name.as_str(), format!("{:x}", hash)))
-        })
-        .filter_map(|s| s.ok())
-        .collect()
+/// Check that registries of all languages are consistent with each other
+/// and with zip files in the dist directory.
+///
+/// All languages must list the same versions of the same packages,
+/// and every release must point to an existing zip file with matching digest.
+/// Zip files not referenced by any registry are reported as orphaned.
+/// Registries which can't be read are returned as error.
+pub async fn verify(path: &str, languages: &[String]) -> Result<Vec<RegistryError>> {
+    // Languages listing each release, keyed by package name and version.
+    let mut releases = BTreeMap::<(String, String), (Release, Vec<&str>)>::new();
+    for lang in languages {
+        let packages = read_registry(format!("{path}/registry_{lang}.json")).await?;
+        info!(
+            "Registry of '{lang}' contains {} package(s).",
+            packages.len()
+        );
+        for (name, package) in packages {
+            for release in package.versions {
+                releases
+                    .entry((name.clone(), release.version.clone()))
+                    .or_insert_with(|| (release, vec![]))
+                    .1
+                    .push(lang);
+            }
+        }
+    }
+
+    let mut errors = vec![];
+    let mut referenced = BTreeSet::new();
+    for ((name, version), (release, listed)) in releases {
+        if listed.len() < languages.len() {
+            errors.push(RegistryError::MissingRelease {
+                name: name.clone(),
+                version: version.clone(),
+                languages: languages
+                    .iter()
+                    .filter(|lang| !listed.contains(&lang.as_str()))
+                    .cloned()
+                    .collect(),
+            });
+        }
+
+        let file = release
+            .dist
+            .url
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .to_owned();
+        referenced.insert(file.clone());
+        match Archive::read(format!("{path}/{file}")) {
+            Ok(archive) if archive.sha256 != release.dist.shasum => {
+                errors.push(RegistryError::DigestMismatch {
+                    name,
+                    version,
+                    expected: release.dist.shasum,
+                    found: archive.sha256,
+                });
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                errors.push(RegistryError::MissingArchive {
+                    name,
+                    version,
+                    file,
+                });
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    let mut entries = fs::read_dir(path).await?;
+    let mut orphans = vec![];
+    while let Some(entry) = entries.next_entry().await? {
+        let file = entry.file_name().to_string_lossy().into_owned();
+        if file.ends_with(".zip") && !referenced.contains(&file) {
+            orphans.push(file);
+        }
+    }
+    orphans.sort();
+    errors.extend(
+        orphans
+            .into_iter()
+            .map(|file| RegistryError::Orphaned { file }),
+    );
+
+    Ok(errors)
 }
 
+/// Update registries of all languages with changes.
+///
+/// Digests of packed plugins are taken from `archives`, keyed by plugin name.
+/// `epoch` is the release date of new versions, as seconds since Unix epoch.
 pub async fn operate_registry<S: AsRef<str>>(
     path: &str,
     plugins_dir: S,
-    updated: &HashMap<String, String>,
+    changes: &ChangeSet,
+    archives: &BTreeMap<String, Archive>,
+    epoch: i64,
     i18n_store: &I18nStore,
+    config: &Config,
 ) -> Result<()> {
-    let hashes = calculate_hashes(path, updated);
+    let released_at = format_release_date(epoch)?;
 
-    for lang in &["en", "zh_CN"] {
-        let path = format!("{path}/registry_{lang}.json");
-        let mut packages = read_registry(&path).await?;
+    let mut registries = BTreeMap::new();
+    for lang in &config.languages {
+        let mut packages = read_or_create_registry(format!("{path}/registry_{lang}.json")).await?;
         update_registry(
             &mut packages,
             &plugins_dir,
-            updated.iter(),
-            &hashes,
+            changes,
+            archives,
             lang,
+            &config.registry.base_url,
+            &released_at,
             i18n_store,
         )
         .await?;
-        write_registry(&path, packages).await?;
+        registries.insert(lang.as_str(), packages);
     }
 
-    Ok(())
+    write_registries(path, &registries, config.registry.schema_version).await
+}
+
+/// Regenerate registries of all languages from all plugins in repository.
+///
+/// Every plugin is published at its current version, with digest taken from `archives`.
+/// Plugins without archive are skipped. If the current version has been listed,
+/// it's regenerated as well, but its release date is kept.
+/// Packages which no longer exist in repository are removed,
+/// while earlier versions of existing packages are kept.
+/// Unreadable registries are replaced.
+pub async fn rebuild_registry<S: AsRef<str>>(
+    path: &str,
+    plugins_dir: S,
+    plugins: &BTreeMap<String, String>,
+    archives: &BTreeMap<String, Archive>,
+    epoch: i64,
+    i18n_store: &I18nStore,
+    config: &Config,
+) -> Result<()> {
+    let released_at = format_release_date(epoch)?;
+
+    let mut registries = BTreeMap::new();
+    for lang in &config.languages {
+        let file = format!("{path}/registry_{lang}.json");
+        let mut packages = match read_or_create_registry(&file).await {
+            Ok(packages) => packages,
+            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                warn!("Previous registry '{file}' is unreadable, so it will be replaced.");
+                BTreeMap::new()
+            }
+            Err(e) => return Err(e),
+        };
+        packages.retain(|name, _| plugins.contains_key(name));
+
+        let mut changes = ChangeSet::default();
+        let mut release_dates = BTreeMap::new();
+        for (name, version) in plugins {
+            if !archives.contains_key(name) {
+                continue;
+            }
+            changes.insert(
+                name.clone(),
+                Change::Added {
+                    version: version.clone(),
+                },
+            );
+
+            let listed = packages.get(name).and_then(|package| {
+                package
+                    .versions
+                    .iter()
+                    .find(|release| &release.version == version)
+            });
+            if let Some(release) = listed {
+                release_dates.insert(name, release.released_at.clone());
+            }
+        }
+
+        update_registry(
+            &mut packages,
+            &plugins_dir,
+            &changes,
+            archives,
+            lang,
+            &config.registry.base_url,
+            &released_at,
+            i18n_store,
+        )
+        .await?;
+        for (name, released_at) in release_dates {
+            let version = &plugins[name];
+            let release = packages.get_mut(name).and_then(|package| {
+                package
+                    .versions
+                    .iter_mut()
+                    .find(|release| &release.version == version)
+            });
+            if let Some(release) = release {
+                release.released_at = released_at;
+            }
+        }
+        registries.insert(lang.as_str(), packages);
+    }
+
+    write_registries(path, &registries, config.registry.schema_version).await
+}
+
+fn format_release_date(epoch: i64) -> Result<String> {
+    OffsetDateTime::from_unix_timestamp(epoch)
+        .ok()
+        .and_then(|time| time.format(&Rfc3339).ok())
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "invalid release date"))
 }
@@ -0,0 +1,126 @@
+use crate::analyzer::{Change, ChangeSet};
+use semver::Version;
+use std::{collections::BTreeMap, error::Error, fmt};
+
+#[derive(Debug)]
+pub enum VersionError {
+    /// The new version isn't a valid semantic version.
+    Invalid {
+        plugin: String,
+        version: String,
+        source: semver::Error,
+    },
+    /// The new version is the same as the published one.
+    Unchanged { plugin: String, version: String },
+    /// The new version is lower than the published one.
+    Downgrade {
+        plugin: String,
+        published: String,
+        version: String,
+    },
+}
+
+impl fmt::Display for VersionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VersionError::Invalid {
+                plugin,
+                version,
+                source,
+            } => write!(
+                f,
+                "version '{version}' of plugin '{plugin}' isn't a valid semver: {source}"
+            ),
+            VersionError::Unchanged { plugin, version } => write!(
+                f,
+                "version '{version}' of plugin '{plugin}' has been published already"
+            ),
+            VersionError::Downgrade {
+                plugin,
+                published,
+                version,
+            } => write!(
+                f,
+                "version of plugin '{plugin}' goes backwards from '{published}' to '{version}'"
+            ),
+        }
+    }
+}
+
+impl Error for VersionError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            VersionError::Invalid { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Check a new version of plugin against the published one.
+///
+/// Unchanged versions and downgrades are allowed only if the update is forced,
+/// while invalid versions are always refused.
+pub fn check(
+    plugin: &str,
+    published: Option<&str>,
+    version: &str,
+    forced: bool,
+) -> Result<(), VersionError> {
+    let parsed = Version::parse(version).map_err(|source| VersionError::Invalid {
+        plugin: plugin.to_owned(),
+        version: version.to_owned(),
+        source,
+    })?;
+
+    let published = match published {
+        Some(published) => published,
+        None => return Ok(()),
+    };
+    let published_parsed = match Version::parse(published) {
+        Ok(published) => published,
+        Err(_) => {
+            warn!("Published version '{published}' of plugin '{plugin}' isn't a valid semver.");
+            return Ok(());
+        }
+    };
+
+    if parsed > published_parsed {
+        return Ok(());
+    }
+
+    let error = if parsed == published_parsed {
+        VersionError::Unchanged {
+            plugin: plugin.to_owned(),
+            version: version.to_owned(),
+        }
+    } else {
+        VersionError::Downgrade {
+            plugin: plugin.to_owned(),
+            published: published.to_owned(),
+            version: version.to_owned(),
+        }
+    };
+    if forced {
+        warn!("Plugin '{plugin}' is forced to update: {error}.");
+        Ok(())
+    } else {
+        Err(error)
+    }
+}
+
+/// Check all plugins to be released against versions in registry.
+pub fn validate(changes: &ChangeSet, published: &BTreeMap<String, String>) -> Vec<VersionError> {
+    changes
+        .iter()
+        .filter_map(|(name, change)| {
+            let published_name = match change {
+                Change::Renamed { from, .. } => from,
+                _ => name,
+            };
+            let published = published.get(published_name).map(String::as_str);
+            change
+                .version()
+                .and_then(|version| check(name, published, version, changes.is_forced(name)).err())
+        })
+        .collect()
+}
@@ -1,38 +1,582 @@
-use std::{fs::File, io, path::Path};
+use crate::{
+    analyzer,
+    config::{SymlinkPolicy, ZipConfig},
+    ignore_rules::IgnoreRules,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeSet,
+    env,
+    error::Error,
+    fmt,
+    fs::{self, File, Metadata},
+    io::{self, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+};
+use time::OffsetDateTime;
 use walkdir::WalkDir;
-use zip::{write::FileOptions, ZipWriter};
+use zip::{
+    result::ZipError, write::FileOptions, CompressionMethod, DateTime, ZipArchive, ZipWriter,
+};
 
-pub fn create_zip<P: AsRef<Path>>(dir: P, dest: P) -> anyhow::Result<()> {
-    info!(
-        "Zipping files for '{}' to '{}'.",
-        dir.as_ref().display(),
-        dest.as_ref().display()
-    );
+/// Compression level of Deflate, fixed so archives don't depend on defaults of libraries.
+const COMPRESSION_LEVEL: i32 = 6;
+/// Permissions of regular files in archives.
+const FILE_MODE: u32 = 0o644;
+/// Permissions of files which are executable by anyone on disk.
+const EXECUTABLE_MODE: u32 = 0o755;
+/// Permissions of directories in archives.
+const DIR_MODE: u32 = 0o755;
+/// Permissions of symbolic links in archives. File type bits are added by zip writer.
+const SYMLINK_MODE: u32 = 0o777;
 
-    let dest = File::create(dest)?;
-    let mut zip = ZipWriter::new(dest);
-    let options = FileOptions::default();
+/// File name of the zip archive of a plugin at a specific version.
+pub fn file_name(name: &str, version: &str) -> String {
+    format!("{name}_{version}.zip")
+}
+
+/// Time of a release as seconds since Unix epoch,
+/// read from `SOURCE_DATE_EPOCH` environment variable or the head commit of repository.
+///
+/// It's used as modification time of entries in archives and release date in registries,
+/// so they don't change when building the same commit again.
+pub fn source_date_epoch(repo_path: impl AsRef<Path>) -> anyhow::Result<i64> {
+    match env::var("SOURCE_DATE_EPOCH") {
+        Ok(epoch) => epoch
+            .trim()
+            .parse()
+            .map_err(|e| anyhow::Error::msg(format!("Invalid SOURCE_DATE_EPOCH '{epoch}': {e}"))),
+        Err(_) => Ok(analyzer::head_commit_time(repo_path)?),
+    }
+}
+
+/// Settings which make archives reproducible,
+/// so identical files always yield byte-identical archives.
+#[derive(Debug, Clone, Copy)]
+pub struct ArchiveOptions {
+    modified: DateTime,
+}
+
+impl ArchiveOptions {
+    /// All entries will have the same modification time,
+    /// given as seconds since Unix epoch.
+    ///
+    /// Zip archives can only represent years from 1980 to 2107,
+    /// so time out of that range falls back to 1980-01-01.
+    pub fn new(epoch: i64) -> ArchiveOptions {
+        let modified = OffsetDateTime::from_unix_timestamp(epoch)
+            .ok()
+            .and_then(|time| DateTime::try_from(time).ok())
+            .unwrap_or_default();
+        ArchiveOptions { modified }
+    }
+
+    fn file_options(&self, mode: u32) -> FileOptions {
+        FileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .compression_level(Some(COMPRESSION_LEVEL))
+            .last_modified_time(self.modified)
+            .unix_permissions(mode)
+    }
+}
+
+/// A file to be packed into zip archive.
+#[derive(Debug)]
+pub struct StagedFile {
+    /// Path of the file on disk.
+    pub source: PathBuf,
+    /// Path in the archive, with `/` as separator.
+    pub name: String,
+    pub kind: StagedKind,
+}
 
-    let walk = WalkDir::new(&dir).into_iter();
+#[derive(Debug, PartialEq, Eq)]
+pub enum StagedKind {
+    /// A regular file, whose content is copied.
+    File { executable: bool },
+    /// A symbolic link stored as link, pointing to a relative path in the plugin.
+    Symlink { target: String },
+}
+
+/// Collect files of a plugin which will be shipped, except those ignored by `rules`.
+///
+/// Names in archive are prefixed with the name of plugin directory,
+/// and files are sorted by their names.
+/// Symbolic links are handled according to `symlinks`.
+/// Nothing on disk is changed.
+pub fn stage(
+    dir: impl AsRef<Path>,
+    rules: &IgnoreRules,
+    symlinks: SymlinkPolicy,
+) -> anyhow::Result<Vec<StagedFile>> {
+    let dir = dir.as_ref();
+    let prefix = dir.file_name().ok_or_else(|| {
+        anyhow::Error::msg(format!(
+            "Cannot find name of directory '{}'.",
+            dir.display()
+        ))
+    })?;
+    let prefix = Path::new(prefix);
+
+    let walk = WalkDir::new(dir)
+        .follow_links(symlinks == SymlinkPolicy::Follow)
+        .into_iter()
+        .filter_entry(|entry| {
+            entry
+                .path()
+                .strip_prefix(dir)
+                .map(|path| {
+                    path.as_os_str().is_empty()
+                        || !rules.is_ignored(path, entry.file_type().is_dir())
+                })
+                .unwrap_or_default()
+        });
+
+    let mut files = vec![];
     for entry in walk {
         let entry = entry?;
         let path = entry.path();
-        let name = path.strip_prefix(dir.as_ref().parent().ok_or_else(|| {
-            anyhow::Error::msg(format!(
-                "Cannot find parent directory of '{}'.",
-                dir.as_ref().display()
-            ))
-        })?)?;
+        let relative = path.strip_prefix(dir)?;
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+        let kind = if entry.path_is_symlink() && symlinks != SymlinkPolicy::Follow {
+            if symlinks == SymlinkPolicy::Reject {
+                anyhow::bail!("Symbolic link '{}' isn't allowed.", path.display());
+            }
+            StagedKind::Symlink {
+                target: link_target(path, relative)?,
+            }
+        } else if entry.file_type().is_file() {
+            StagedKind::File {
+                executable: is_executable(&entry.metadata()?),
+            }
+        } else {
+            continue;
+        };
+
+        files.push(StagedFile {
+            source: path.to_path_buf(),
+            name: archive_name(&prefix.join(relative)),
+            kind,
+        });
+    }
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(files)
+}
+
+/// Read target of a symbolic link, which must be a relative path inside the plugin.
+fn link_target(path: &Path, relative: &Path) -> anyhow::Result<String> {
+    let target = fs::read_link(path)?;
+
+    let mut depth = relative.components().count() - 1;
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir if depth > 0 => depth -= 1,
+            _ => anyhow::bail!(
+                "Symbolic link '{}' points to '{}', which is outside of the plugin.",
+                path.display(),
+                target.display()
+            ),
+        }
+    }
+
+    Ok(archive_name(&target))
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_: &Metadata) -> bool {
+    false
+}
+
+/// A zip archive written to disk.
+#[derive(Debug, Clone)]
+pub struct Archive {
+    pub path: PathBuf,
+    /// Size in bytes.
+    pub size: u64,
+    /// Hex-encoded SHA-256 digest.
+    pub sha256: String,
+}
+
+impl Archive {
+    /// Read an existing archive on disk and calculate its digest.
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Archive> {
+        let path = path.as_ref();
+        let mut hasher = Sha256::new();
+        let size = io::copy(&mut File::open(path)?, &mut hasher)?;
+        Ok(Archive {
+            path: path.to_path_buf(),
+            size,
+            sha256: format!("{:x}", hasher.finalize()),
+        })
+    }
+}
+
+/// Pack staged files into zip archive.
+///
+/// Directories are created from names of files, so empty directories are omitted.
+/// Entries are written in the given order.
+///
+/// The archive is written to a temporary file next to `dest`, which is renamed when finished,
+/// so an interrupted run never leaves a truncated archive behind.
+/// Its digest is calculated while writing, without reading the archive back.
+pub fn create_zip(
+    files: &[StagedFile],
+    dest: impl AsRef<Path>,
+    options: &ArchiveOptions,
+) -> anyhow::Result<Archive> {
+    let dest = dest.as_ref();
+    info!("Zipping files to '{}'.", dest.display());
+
+    let mut temp = dest.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+
+    let (size, sha256) = match write_zip(files, &temp, options) {
+        Ok(written) => written,
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            return Err(e);
+        }
+    };
+    fs::rename(&temp, dest)?;
+
+    Ok(Archive {
+        path: dest.to_path_buf(),
+        size,
+        sha256,
+    })
+}
+
+/// Write zip archive to `dest`, returning its size and hex-encoded SHA-256 digest.
+fn write_zip(
+    files: &[StagedFile],
+    dest: &Path,
+    options: &ArchiveOptions,
+) -> anyhow::Result<(u64, String)> {
+    let mut zip = ZipWriter::new(HashingWriter::new(BufWriter::new(File::create(dest)?)));
+
+    let mut dirs = BTreeSet::new();
+    for file in files {
+        for (index, _) in file.name.match_indices('/') {
+            let dir = &file.name[..=index];
+            if dirs.insert(dir.to_owned()) {
+                zip.add_directory(dir, options.file_options(DIR_MODE))?;
+            }
+        }
+
+        match &file.kind {
+            StagedKind::File { executable } => {
+                let mode = if *executable {
+                    EXECUTABLE_MODE
+                } else {
+                    FILE_MODE
+                };
+                zip.start_file(&file.name, options.file_options(mode))?;
+                let mut f = File::open(&file.source)?;
+                io::copy(&mut f, &mut zip)?;
+            }
+            StagedKind::Symlink { target } => {
+                zip.add_symlink(&file.name, target, options.file_options(SYMLINK_MODE))?;
+            }
+        }
+    }
+
+    let (writer, size, sha256) = zip.finish()?.finish();
+    writer.into_inner()?.sync_all()?;
+
+    Ok((size, sha256))
+}
+
+/// Writer which calculates SHA-256 digest of data written through it.
+///
+/// Zip writers seek back to fill in the header of an entry after writing its data,
+/// so data after the latest position sought back to is kept until the next backward seek,
+/// when it can't be changed anymore. At most about one entry is kept at a time.
+/// Writing data before that position again fails instead of yielding a wrong digest.
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+    /// Number of bytes which are hashed already.
+    hashed: u64,
+    /// Data written after `hashed` bytes.
+    pending: Vec<u8>,
+    position: u64,
+}
+
+impl<W> HashingWriter<W> {
+    fn new(inner: W) -> Self {
+        HashingWriter {
+            inner,
+            hasher: Sha256::new(),
+            hashed: 0,
+            pending: vec![],
+            position: 0,
+        }
+    }
+
+    /// Hash data before `position`, which won't be written again.
+    fn commit(&mut self, position: u64) {
+        let len = (position.saturating_sub(self.hashed) as usize).min(self.pending.len());
+        self.hasher.update(&self.pending[..len]);
+        self.pending.drain(..len);
+        self.hashed += len as u64;
+    }
+
+    /// Return the inner writer, with size and hex-encoded SHA-256 digest of written data.
+    fn finish(mut self) -> (W, u64, String) {
+        self.commit(u64::MAX);
+        (
+            self.inner,
+            self.hashed,
+            format!("{:x}", self.hasher.finalize()),
+        )
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.position < self.hashed {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "data which is hashed already can't be written again",
+            ));
+        }
+
+        let written = self.inner.write(buf)?;
+        let start = (self.position - self.hashed) as usize;
+        let end = start + written;
+        if self.pending.len() < end {
+            self.pending.resize(end, 0);
+        }
+        self.pending[start..end].copy_from_slice(&buf[..written]);
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Seek> Seek for HashingWriter<W> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = self.inner.seek(pos)?;
+        if position < self.position {
+            self.commit(position);
+        }
+        self.position = position;
+        Ok(position)
+    }
+}
+
+fn archive_name(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Files and directories which must never be shipped.
+const FORBIDDEN: &[&str] = &["node_modules", ".git", ".env"];
 
-        if path.is_file() {
-            zip.start_file(name.to_string_lossy(), options)?;
-            let mut f = File::open(path)?;
-            io::copy(&mut f, &mut zip)?;
-        } else if !name.as_os_str().is_empty() {
-            zip.add_directory(name.to_string_lossy(), options)?;
+const MIB: u64 = 1024 * 1024;
+
+/// Problem of a zip archive found by [`verify`].
+#[derive(Debug)]
+pub struct ArchiveError {
+    /// File name of the archive.
+    pub archive: String,
+    pub kind: ArchiveErrorKind,
+}
+
+#[derive(Debug)]
+pub enum ArchiveErrorKind {
+    /// The archive can't be read.
+    Unreadable(ZipError),
+    /// An entry isn't inside the top-level directory named after the plugin.
+    OutsideRoot { entry: String },
+    /// There's no `package.json` in the top-level directory.
+    MissingManifest,
+    /// `package.json` can't be parsed.
+    MalformedManifest(serde_json::Error),
+    /// A field of `package.json` doesn't match the archive.
+    ManifestMismatch {
+        field: &'static str,
+        expected: String,
+        found: String,
+    },
+    /// An entry which must never be shipped.
+    Forbidden { entry: String },
+    /// The archive or its extracted files are too large. Sizes are in bytes.
+    TooLarge {
+        what: &'static str,
+        size: u64,
+        limit: u64,
+    },
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "archive '{}' ", self.archive)?;
+        match &self.kind {
+            ArchiveErrorKind::Unreadable(e) => write!(f, "can't be read: {e}"),
+            ArchiveErrorKind::OutsideRoot { entry } => {
+                write!(f, "contains '{entry}' outside of the plugin directory")
+            }
+            ArchiveErrorKind::MissingManifest => write!(f, "doesn't contain 'package.json'"),
+            ArchiveErrorKind::MalformedManifest(e) => {
+                write!(f, "contains malformed 'package.json': {e}")
+            }
+            ArchiveErrorKind::ManifestMismatch {
+                field,
+                expected,
+                found,
+            } => write!(
+                f,
+                "has '{field}' of '{found}' in 'package.json', but '{expected}' is expected"
+            ),
+            ArchiveErrorKind::Forbidden { entry } => {
+                write!(f, "contains '{entry}' which must not be shipped")
+            }
+            ArchiveErrorKind::TooLarge { what, size, limit } => write!(
+                f,
+                "has {what} of {size} bytes, which exceeds the limit of {limit} bytes"
+            ),
+        }
+    }
+}
+
+impl Error for ArchiveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            ArchiveErrorKind::Unreadable(e) => Some(e),
+            ArchiveErrorKind::MalformedManifest(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    name: String,
+    version: String,
+}
+
+/// Check that a zip archive can be installed by Blessing Skin as the given plugin.
+///
+/// It must have a single top-level directory named after the plugin,
+/// with a `package.json` whose name and version match,
+/// and mustn't contain forbidden files or exceed size limits in config.
+/// All problems found are returned.
+pub fn verify(
+    path: impl AsRef<Path>,
+    name: &str,
+    version: &str,
+    config: &ZipConfig,
+) -> Vec<ArchiveError> {
+    let path = path.as_ref();
+    let archive = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let error = |kind| ArchiveError {
+        archive: archive.clone(),
+        kind,
+    };
+
+    let mut zip = match File::open(path)
+        .map_err(ZipError::from)
+        .and_then(ZipArchive::new)
+    {
+        Ok(zip) => zip,
+        Err(e) => return vec![error(ArchiveErrorKind::Unreadable(e))],
+    };
+    let mut errors = vec![];
+
+    let size = fs::metadata(path)
+        .map(|metadata| metadata.len())
+        .unwrap_or_default();
+    if config.max_size > 0 && size > config.max_size * MIB {
+        errors.push(error(ArchiveErrorKind::TooLarge {
+            what: "size",
+            size,
+            limit: config.max_size * MIB,
+        }));
+    }
+
+    let root = format!("{name}/");
+    let mut unpacked_size = 0;
+    for index in 0..zip.len() {
+        let entry = match zip.by_index(index) {
+            Ok(entry) => entry,
+            Err(e) => {
+                errors.push(error(ArchiveErrorKind::Unreadable(e)));
+                return errors;
+            }
+        };
+        let entry_name = entry.name().to_owned();
+        unpacked_size += entry.size();
+
+        if entry.enclosed_name().is_none() || !entry_name.starts_with(&root) {
+            errors.push(error(ArchiveErrorKind::OutsideRoot { entry: entry_name }));
+        } else if entry_name
+            .split('/')
+            .any(|component| FORBIDDEN.contains(&component))
+        {
+            errors.push(error(ArchiveErrorKind::Forbidden { entry: entry_name }));
+        }
+    }
+    if config.max_unpacked_size > 0 && unpacked_size > config.max_unpacked_size * MIB {
+        errors.push(error(ArchiveErrorKind::TooLarge {
+            what: "extracted size",
+            size: unpacked_size,
+            limit: config.max_unpacked_size * MIB,
+        }));
+    }
+
+    let manifest = match zip.by_name(&format!("{root}package.json")) {
+        Ok(mut entry) => {
+            let mut content = vec![];
+            entry
+                .read_to_end(&mut content)
+                .map_err(|e| ArchiveErrorKind::Unreadable(e.into()))
+                .and_then(|_| {
+                    serde_json::from_slice::<Manifest>(&content)
+                        .map_err(ArchiveErrorKind::MalformedManifest)
+                })
+        }
+        Err(ZipError::FileNotFound) => Err(ArchiveErrorKind::MissingManifest),
+        Err(e) => Err(ArchiveErrorKind::Unreadable(e)),
+    };
+    match manifest {
+        Ok(manifest) => {
+            for (field, expected, found) in [
+                ("name", name, manifest.name),
+                ("version", version, manifest.version),
+            ] {
+                if found != expected {
+                    errors.push(error(ArchiveErrorKind::ManifestMismatch {
+                        field,
+                        expected: expected.to_owned(),
+                        found,
+                    }));
+                }
+            }
         }
+        Err(kind) => errors.push(error(kind)),
     }
 
-    zip.finish()?;
-    Ok(())
+    errors
 }
@@ -1,4 +1,8 @@
-use git2::{Error, Repository, Signature};
+use git2::{Error, Oid, Repository, Signature};
+use nano::analyzer::{
+    analyze_commit_messages, analyze_commits, AnalyzerError, Change, ChangeSet, Commit,
+};
+use serde_json::json;
 use std::{env::temp_dir, io::ErrorKind, path::Path};
 use tokio::fs;
 
@@ -26,8 +30,8 @@ async fn no_plugins_updated() -> anyhow::Result<()> {
     let sig = signature()?;
     repo.commit(Some("HEAD"), &sig, &sig, "update", &tree, &[&commit])?;
 
-    let (msg, updated) = nano::analyzer::analyze(&path)?;
-    assert_eq!(&msg, "update");
+    let (commits, updated) = nano::analyzer::analyze(&path, None)?;
+    assert_eq!(messages(&commits), ["update"]);
     assert!(updated.is_empty());
 
     Ok(())
@@ -53,7 +57,7 @@ async fn some_plugins_updated() -> anyhow::Result<()> {
     fs::create_dir(&plugin_dir).await?;
     fs::write(
         format!("{}/package.json", plugin_dir),
-        b"{\n  \"version\": \"1.0.0\"\n}",
+        manifest("test", "1.0.0"),
     )
     .await?;
     let mut index = repo.index()?;
@@ -65,13 +69,587 @@ async fn some_plugins_updated() -> anyhow::Result<()> {
     let sig = signature()?;
     repo.commit(Some("HEAD"), &sig, &sig, "update", &tree, &[&commit])?;
 
-    let (msg, updated) = nano::analyzer::analyze(&path)?;
-    assert_eq!(&msg, "update");
-    assert_eq!(updated.get("test").unwrap().as_str(), "1.0.0");
+    let (commits, updated) = nano::analyzer::analyze(&path, None)?;
+    assert_eq!(messages(&commits), ["update"]);
+    assert_eq!(updated.get("test"), Some(&added("1.0.0")));
+
+    let (commits, updated) = nano::analyzer::analyze(&path, Some(""))?;
+    assert_eq!(messages(&commits), ["update"]);
+    assert_eq!(updated.get("test"), Some(&added("1.0.0")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn updated_in_commit_range() -> anyhow::Result<()> {
+    let mut path = temp_dir();
+    path.push("bs-plugins_range_updated");
+
+    let repo = init_repo(&path).await?;
+
+    fs::write(format!("{}/text", path.display()), b"1").await?;
+    let mut index = repo.index()?;
+    let tree_id = index.write_tree()?;
+    let tree = repo.find_tree(tree_id)?;
+
+    let sig = signature()?;
+    let base_id = repo.commit(Some("HEAD"), &sig, &sig, "init", &tree, &[])?;
+
+    fs::create_dir(format!("{}/plugins", path.display())).await?;
+    let mut parent_id = base_id;
+    for (plugin, version) in [("a", "1.0.0"), ("b", "1.0.0"), ("a", "1.1.0")] {
+        let plugin_dir = format!("{}/plugins/{plugin}", path.display());
+        fs::create_dir_all(&plugin_dir).await?;
+        fs::write(
+            format!("{}/package.json", plugin_dir),
+            manifest(plugin, version),
+        )
+        .await?;
+        let mut index = repo.index()?;
+        index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
+        let tree_id = index.write_tree()?;
+        let tree = repo.find_tree(tree_id)?;
+
+        let parent = repo.find_commit(parent_id)?;
+        parent_id = repo.commit(Some("HEAD"), &sig, &sig, plugin, &tree, &[&parent])?;
+    }
+
+    let (_, updated) = nano::analyzer::analyze(&path, None)?;
+    assert_eq!(updated.len(), 1);
+    assert_eq!(updated.get("a"), Some(&updated_to("1.1.0")));
+
+    let base = base_id.to_string();
+    let (commits, updated) = nano::analyzer::analyze(&path, Some(&base))?;
+    assert_eq!(messages(&commits), ["a", "b", "a"]);
+    assert!(commits
+        .iter()
+        .all(|commit| commit.bumped.contains(&commit.message)));
+    assert_eq!(updated.len(), 2);
+    assert_eq!(updated.get("a"), Some(&added("1.1.0")));
+    assert_eq!(updated.get("b"), Some(&added("1.0.0")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn only_top_level_version_counts() -> anyhow::Result<()> {
+    let mut path = temp_dir();
+    path.push("bs-plugins_top_level_version");
+
+    let repo = init_repo(&path).await?;
+
+    fs::create_dir(format!("{}/plugins", path.display())).await?;
+    for plugin in ["nested", "minified"] {
+        let plugin_dir = format!("{}/plugins/{plugin}", path.display());
+        fs::create_dir(&plugin_dir).await?;
+        fs::write(
+            format!("{}/package.json", plugin_dir),
+            manifest(plugin, "1.0.0"),
+        )
+        .await?;
+    }
+    let mut index = repo.index()?;
+    index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
+    let tree_id = index.write_tree()?;
+    let tree = repo.find_tree(tree_id)?;
+
+    let sig = signature()?;
+    let commit_id = repo.commit(Some("HEAD"), &sig, &sig, "init", &tree, &[])?;
+
+    // Only a nested `version` field changes here.
+    fs::write(
+        format!("{}/plugins/nested/package.json", path.display()),
+        serde_json::to_vec_pretty(&json!({
+            "name": "nested",
+            "version": "1.0.0",
+            "title": "nested",
+            "description": "",
+            "author": "",
+            "require": { "blessing-skin-server": "^6.0.0", "version": "2.0.0" }
+        }))?,
+    )
+    .await?;
+    // Minified, with keys reordered and a pre-release version.
+    fs::write(
+        format!("{}/plugins/minified/package.json", path.display()),
+        br#"{"require":{},"author":"","description":"","title":"minified","version":"1.1.0-beta.1+build.2","name":"minified"}"#,
+    )
+    .await?;
+    let mut index = repo.index()?;
+    index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
+    let tree_id = index.write_tree()?;
+    let tree = repo.find_tree(tree_id)?;
+
+    let commit = repo.find_commit(commit_id)?;
+    repo.commit(Some("HEAD"), &sig, &sig, "update", &tree, &[&commit])?;
+
+    let (_, updated) = nano::analyzer::analyze(&path, None)?;
+    assert_eq!(updated.len(), 1);
+    assert_eq!(
+        updated.get("minified"),
+        Some(&updated_to("1.1.0-beta.1+build.2"))
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn plugins_removed_and_renamed() -> anyhow::Result<()> {
+    let mut path = temp_dir();
+    path.push("bs-plugins_removed_renamed");
+
+    let repo = init_repo(&path).await?;
+
+    fs::create_dir(format!("{}/plugins", path.display())).await?;
+    for plugin in ["kept", "deleted", "old-name"] {
+        let plugin_dir = format!("{}/plugins/{plugin}", path.display());
+        fs::create_dir(&plugin_dir).await?;
+        fs::write(
+            format!("{}/package.json", plugin_dir),
+            manifest(plugin, "1.0.0"),
+        )
+        .await?;
+        fs::write(
+            format!("{}/bootstrap.php", plugin_dir),
+            format!("<?php\n\n// Bootstrap of {plugin}.\nreturn function () {{}};\n"),
+        )
+        .await?;
+    }
+    let mut index = repo.index()?;
+    index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
+    let tree_id = index.write_tree()?;
+    let tree = repo.find_tree(tree_id)?;
+
+    let sig = signature()?;
+    let commit_id = repo.commit(Some("HEAD"), &sig, &sig, "init", &tree, &[])?;
+
+    fs::remove_dir_all(format!("{}/plugins/deleted", path.display())).await?;
+    fs::rename(
+        format!("{}/plugins/old-name", path.display()),
+        format!("{}/plugins/new-name", path.display()),
+    )
+    .await?;
+    let mut index = repo.index()?;
+    index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
+    index.update_all(["*"].iter(), None)?;
+    let tree_id = index.write_tree()?;
+    let tree = repo.find_tree(tree_id)?;
+
+    let commit = repo.find_commit(commit_id)?;
+    repo.commit(Some("HEAD"), &sig, &sig, "update", &tree, &[&commit])?;
+
+    let (_, changes) = nano::analyzer::analyze(&path, None)?;
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes.get("deleted"), Some(&Change::Removed));
+    assert_eq!(
+        changes.get("new-name"),
+        Some(&Change::Renamed {
+            from: "old-name".to_string(),
+            version: "1.0.0".to_string()
+        })
+    );
+    assert_eq!(
+        changes.to_build().collect::<Vec<_>>(),
+        vec![(&"new-name".to_string(), &"1.0.0".to_string())]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn unrelated_plugins_not_renamed() -> anyhow::Result<()> {
+    let mut path = temp_dir();
+    path.push("bs-plugins_unrelated_not_renamed");
+
+    let repo = init_repo(&path).await?;
+
+    let old_dir = format!("{}/plugins/old-plugin", path.display());
+    fs::create_dir_all(&old_dir).await?;
+    fs::write(
+        format!("{old_dir}/package.json"),
+        manifest("old-plugin", "2.0.0"),
+    )
+    .await?;
+    fs::write(
+        format!("{old_dir}/bootstrap.php"),
+        b"<?php\n\necho 'old';\n",
+    )
+    .await?;
+    let mut index = repo.index()?;
+    index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
+    let tree_id = index.write_tree()?;
+    let tree = repo.find_tree(tree_id)?;
+
+    let sig = signature()?;
+    let commit_id = repo.commit(Some("HEAD"), &sig, &sig, "init", &tree, &[])?;
+
+    fs::remove_dir_all(&old_dir).await?;
+    let new_dir = format!("{}/plugins/brand-new", path.display());
+    fs::create_dir_all(&new_dir).await?;
+    fs::write(
+        format!("{new_dir}/package.json"),
+        manifest("brand-new", "1.0.0"),
+    )
+    .await?;
+    fs::write(
+        format!("{new_dir}/index.js"),
+        b"console.log('something else entirely')\n",
+    )
+    .await?;
+    let mut index = repo.index()?;
+    index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
+    index.update_all(["*"].iter(), None)?;
+    let tree_id = index.write_tree()?;
+    let tree = repo.find_tree(tree_id)?;
+
+    let commit = repo.find_commit(commit_id)?;
+    repo.commit(Some("HEAD"), &sig, &sig, "update", &tree, &[&commit])?;
+
+    let (_, changes) = nano::analyzer::analyze(&path, None)?;
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes.get("old-plugin"), Some(&Change::Removed));
+    assert_eq!(changes.get("brand-new"), Some(&added("1.0.0")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn non_plugin_manifests() -> anyhow::Result<()> {
+    let mut path = temp_dir();
+    path.push("bs-plugins_non_plugin_manifests");
+
+    let repo = init_repo(&path).await?;
+
+    fs::write(format!("{}/text", path.display()), b"1").await?;
+    let mut index = repo.index()?;
+    let tree_id = index.write_tree()?;
+    let tree = repo.find_tree(tree_id)?;
+
+    let sig = signature()?;
+    let commit_id = repo.commit(Some("HEAD"), &sig, &sig, "init", &tree, &[])?;
+
+    fs::write(format!("{}/package.json", path.display()), b"{}").await?;
+    fs::create_dir_all(format!("{}/plugins/test/assets", path.display())).await?;
+    fs::write(
+        format!("{}/plugins/test/assets/package.json", path.display()),
+        b"{}",
+    )
+    .await?;
+    let mut index = repo.index()?;
+    index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
+    let tree_id = index.write_tree()?;
+    let tree = repo.find_tree(tree_id)?;
+
+    let commit = repo.find_commit(commit_id)?;
+    let commit_id = repo.commit(Some("HEAD"), &sig, &sig, "update", &tree, &[&commit])?;
+
+    let (_, changes) = nano::analyzer::analyze(&path, None)?;
+    assert!(changes.is_empty());
+
+    fs::write(
+        format!("{}/plugins/test/package.json", path.display()),
+        b"{\"version\": \"1.0.0\"}",
+    )
+    .await?;
+    let mut index = repo.index()?;
+    index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
+    let tree_id = index.write_tree()?;
+    let tree = repo.find_tree(tree_id)?;
+
+    let commit = repo.find_commit(commit_id)?;
+    repo.commit(Some("HEAD"), &sig, &sig, "malformed", &tree, &[&commit])?;
+
+    match nano::analyzer::analyze(&path, None) {
+        Err(AnalyzerError::MalformedManifest { plugin, .. }) => assert_eq!(plugin, "test"),
+        result => panic!("unexpected result: {result:?}"),
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn non_utf8_paths() -> anyhow::Result<()> {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let mut path = temp_dir();
+    path.push("bs-plugins_non_utf8_paths");
+
+    let repo = init_repo(&path).await?;
+
+    fs::write(format!("{}/text", path.display()), b"1").await?;
+    let mut index = repo.index()?;
+    let tree_id = index.write_tree()?;
+    let tree = repo.find_tree(tree_id)?;
+
+    let sig = signature()?;
+    let commit_id = repo.commit(Some("HEAD"), &sig, &sig, "init", &tree, &[])?;
+
+    let plugin_dir = format!("{}/plugins/test", path.display());
+    fs::create_dir_all(&plugin_dir).await?;
+    fs::write(
+        format!("{plugin_dir}/package.json"),
+        manifest("test", "1.0.0"),
+    )
+    .await?;
+    fs::write(
+        Path::new(&plugin_dir).join(OsStr::from_bytes(b"caf\xe9.txt")),
+        b"1",
+    )
+    .await?;
+    let mut index = repo.index()?;
+    index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
+    let tree_id = index.write_tree()?;
+    let tree = repo.find_tree(tree_id)?;
+
+    let commit = repo.find_commit(commit_id)?;
+    repo.commit(Some("HEAD"), &sig, &sig, "update", &tree, &[&commit])?;
+
+    let (_, changes) = nano::analyzer::analyze(&path, None)?;
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes.get("test"), Some(&added("1.0.0")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn messages_of_merged_commits() -> anyhow::Result<()> {
+    let mut path = temp_dir();
+    path.push("bs-plugins_merged_commits");
+
+    let repo = init_repo(&path).await?;
+
+    fs::write(format!("{}/text", path.display()), b"1").await?;
+    let mut index = repo.index()?;
+    let tree_id = index.write_tree()?;
+    let tree = repo.find_tree(tree_id)?;
+
+    let sig = signature()?;
+    let base_id = repo.commit(Some("HEAD"), &sig, &sig, "init", &tree, &[])?;
+    let base = repo.find_commit(base_id)?;
+
+    let feature_id = repo.commit(
+        None,
+        &sig,
+        &sig,
+        "fix bug\n\nNano-Force-Update: yggdrasil-api",
+        &tree,
+        &[&base],
+    )?;
+    let feature = repo.find_commit(feature_id)?;
+    repo.commit(
+        Some("HEAD"),
+        &sig,
+        &sig,
+        "Merge pull request #1",
+        &tree,
+        &[&base, &feature],
+    )?;
+
+    let (commits, changes) = nano::analyzer::analyze(&path, None)?;
+    assert!(changes.is_empty());
+    assert_eq!(
+        messages(&commits),
+        [
+            "Merge pull request #1",
+            "fix bug\n\nNano-Force-Update: yggdrasil-api"
+        ]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn skipped_commit_in_range() -> anyhow::Result<()> {
+    let mut path = temp_dir();
+    path.push("bs-plugins_skipped_in_range");
+
+    let repo = init_repo(&path).await?;
+
+    fs::write(format!("{}/text", path.display()), b"1").await?;
+    let mut index = repo.index()?;
+    index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
+    let tree_id = index.write_tree()?;
+    let tree = repo.find_tree(tree_id)?;
+
+    let sig = signature()?;
+    let base_id = repo.commit(Some("HEAD"), &sig, &sig, "init", &tree, &[])?;
+
+    let plugin_dir = format!("{}/plugins/test", path.display());
+    fs::create_dir_all(&plugin_dir).await?;
+    fs::write(
+        format!("{plugin_dir}/package.json"),
+        manifest("test", "1.0.0"),
+    )
+    .await?;
+    let mut index = repo.index()?;
+    index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
+    let tree_id = index.write_tree()?;
+    let tree = repo.find_tree(tree_id)?;
+
+    let commit = repo.find_commit(base_id)?;
+    let commit_id = repo.commit(Some("HEAD"), &sig, &sig, "add test", &tree, &[&commit])?;
+
+    fs::write(format!("{}/text", path.display()), b"2").await?;
+    let mut index = repo.index()?;
+    index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
+    let tree_id = index.write_tree()?;
+    let tree = repo.find_tree(tree_id)?;
+
+    let commit = repo.find_commit(commit_id)?;
+    repo.commit(
+        Some("HEAD"),
+        &sig,
+        &sig,
+        "[nano skip] docs",
+        &tree,
+        &[&commit],
+    )?;
+
+    let (commits, mut changes) = nano::analyzer::analyze(&path, Some(&base_id.to_string()))?;
+    assert_eq!(messages(&commits), ["[nano skip] docs", "add test"]);
+    analyze_commits(&commits, &path, &mut changes).await?;
+    assert_eq!(changes.get("test"), Some(&added("1.0.0")));
+
+    let (commits, mut changes) = nano::analyzer::analyze(&path, Some(&commit_id.to_string()))?;
+    analyze_commits(&commits, &path, &mut changes).await?;
+    assert!(changes.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn skip_build_in_merged_commits() -> anyhow::Result<()> {
+    let mut path = temp_dir();
+    path.push("bs-plugins_skip_build_merged");
+
+    let repo = init_repo(&path).await?;
+    let plugin_dir = format!("{}/plugins/test", path.display());
+    fs::create_dir_all(&plugin_dir).await?;
+    let package_json = format!("{plugin_dir}/package.json");
+
+    fs::write(&package_json, manifest("test", "1.0.0")).await?;
+    let base_id = commit_all(&repo, Some("HEAD"), "init", &[])?;
+    let base = repo.find_commit(base_id)?;
+
+    fs::write(&package_json, manifest("test", "1.1.0")).await?;
+    let feature_id = commit_all(&repo, None, "bump test\n\nskip build: test", &[&base])?;
+    let feature = repo.find_commit(feature_id)?;
+    let merge_id = commit_all(
+        &repo,
+        Some("HEAD"),
+        "Merge pull request #1",
+        &[&base, &feature],
+    )?;
+
+    let (commits, mut changes) = nano::analyzer::analyze(&path, Some(&base_id.to_string()))?;
+    assert!(commits[0].bumped.is_empty());
+    assert_eq!(changes.get("test"), Some(&updated_to("1.1.0")));
+    analyze_commits(&commits, &path, &mut changes).await?;
+    assert!(changes.is_empty());
+
+    fs::write(&package_json, manifest("test", "1.2.0")).await?;
+    let merge = repo.find_commit(merge_id)?;
+    commit_all(&repo, Some("HEAD"), "bump test again", &[&merge])?;
+
+    let (commits, mut changes) = nano::analyzer::analyze(&path, Some(&base_id.to_string()))?;
+    analyze_commits(&commits, &path, &mut changes).await?;
+    assert_eq!(changes.get("test"), Some(&updated_to("1.2.0")));
 
     Ok(())
 }
 
+#[tokio::test]
+async fn commit_message_directives() -> anyhow::Result<()> {
+    let mut path = temp_dir();
+    path.push("bs-plugins_directives");
+
+    match fs::remove_dir_all(&path).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        e => panic!("{e:?}"),
+    };
+    for plugin in ["a", "b", "c"] {
+        let plugin_dir = format!("{}/plugins/{plugin}", path.display());
+        fs::create_dir_all(&plugin_dir).await?;
+        fs::write(
+            format!("{}/package.json", plugin_dir),
+            manifest(plugin, "1.0.0"),
+        )
+        .await?;
+    }
+
+    let mut changes = ChangeSet::default();
+    changes.insert("c".to_string(), updated_to("1.0.0"));
+    analyze_commit_messages(["force update: a", "skip build: c"], &path, &mut changes).await?;
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes.get("a"), Some(&updated_to("1.0.0")));
+
+    let mut changes = ChangeSet::default();
+    analyze_commit_messages(["force update: *"], &path, &mut changes).await?;
+    assert_eq!(changes.len(), 3);
+
+    analyze_commit_messages(["[nano skip]"], &path, &mut changes).await?;
+    assert!(changes.is_empty());
+
+    match analyze_commit_messages(["force update: d"], &path, &mut changes).await {
+        Err(AnalyzerError::UnknownPlugin(name)) => assert_eq!(name, "d"),
+        result => panic!("unexpected result: {result:?}"),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+fn commit_all(
+    repo: &Repository,
+    update_ref: Option<&str>,
+    message: &str,
+    parents: &[&git2::Commit],
+) -> Result<Oid, Error> {
+    let mut index = repo.index()?;
+    index.add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)?;
+    let tree_id = index.write_tree()?;
+    let tree = repo.find_tree(tree_id)?;
+    let sig = signature()?;
+    repo.commit(update_ref, &sig, &sig, message, &tree, parents)
+}
+
+#[cfg(test)]
+fn messages(commits: &[Commit]) -> Vec<&str> {
+    commits
+        .iter()
+        .map(|commit| commit.message.as_str())
+        .collect()
+}
+
+#[cfg(test)]
+fn added(version: &str) -> Change {
+    Change::Added {
+        version: version.to_string(),
+    }
+}
+
+#[cfg(test)]
+fn updated_to(version: &str) -> Change {
+    Change::Updated {
+        version: version.to_string(),
+    }
+}
+
+#[cfg(test)]
+fn manifest(name: &str, version: &str) -> Vec<u8> {
+    serde_json::to_vec_pretty(&json!({
+        "name": name,
+        "version": version,
+        "title": name,
+        "description": "",
+        "author": "",
+        "require": { "blessing-skin-server": "^6.0.0" }
+    }))
+    .unwrap()
+}
+
 #[cfg(test)]
 async fn init_repo(path: impl AsRef<Path>) -> Result<Repository, Error> {
     match fs::remove_dir_all(&path).await {
@@ -1,46 +1,41 @@
-use futures::future::try_join;
+use nano::config::{Config, PackageManager};
 use std::{env::temp_dir, io::ErrorKind};
 use tokio::{fs, io::Result};
 
 #[tokio::test]
-async fn clean_up() -> Result<()> {
+async fn detect_package_manager() -> Result<()> {
     let mut path = temp_dir();
-    path.push("clean_up-test");
+    path.push("detect_package_manager-test");
 
     match fs::remove_dir_all(&path).await {
         Ok(_) => {}
         Err(e) if e.kind() == ErrorKind::NotFound => {}
         e => panic!("{e:?}"),
     };
+    fs::create_dir_all(&path).await?;
 
-    let display = path.display();
-
-    let node_modules = format!("{}/node_modules", display);
-    fs::create_dir_all(&node_modules).await?;
-
-    let git_ignore = format!("{}/.gitignore", display);
-    fs::write(&git_ignore, b"").await?;
-
-    let assets = format!("{}/assets", display);
-    fs::create_dir(&assets).await?;
-    let js = format!("{}/file.js", assets);
-    let ts = format!("{}/file.ts", assets);
-    try_join(fs::write(&js, b""), fs::write(&ts, b"")).await?;
-
-    nano::build::clean_up(&path).await;
+    let mut config = Config::default().build;
+    assert_eq!(
+        nano::build::detect_package_manager(&path, &config).await,
+        PackageManager::Pnpm
+    );
 
+    fs::write(path.join("package-lock.json"), b"{}").await?;
     assert_eq!(
-        fs::File::open(&node_modules).await.unwrap_err().kind(),
-        ErrorKind::NotFound
+        nano::build::detect_package_manager(&path, &config).await,
+        PackageManager::Npm
     );
+
+    fs::write(path.join("yarn.lock"), b"").await?;
     assert_eq!(
-        fs::File::open(&git_ignore).await.unwrap_err().kind(),
-        ErrorKind::NotFound
+        nano::build::detect_package_manager(&path, &config).await,
+        PackageManager::Yarn
     );
-    assert!(fs::File::open(&js).await.is_ok());
+
+    config.package_manager = Some(PackageManager::Npm);
     assert_eq!(
-        fs::File::open(&ts).await.unwrap_err().kind(),
-        ErrorKind::NotFound
+        nano::build::detect_package_manager(&path, &config).await,
+        PackageManager::Npm
     );
 
     Ok(())
@@ -0,0 +1,46 @@
+use git2::{Repository, Signature};
+use std::{env::temp_dir, io::ErrorKind, path::Path, process::Command};
+use tokio::fs;
+
+#[tokio::test]
+async fn base_ref_of_default_command() -> anyhow::Result<()> {
+    let mut path = temp_dir();
+    path.push("bs-plugins_cli_base_ref");
+
+    match fs::remove_dir_all(&path).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        e => panic!("{e:?}"),
+    };
+    let repo = Repository::init(&path)?;
+    let tree_id = repo.index()?.write_tree()?;
+    let tree = repo.find_tree(tree_id)?;
+    let sig = Signature::now("git", "m@git.me")?;
+    let base_id = repo.commit(Some("HEAD"), &sig, &sig, "init", &tree, &[])?;
+    let base = repo.find_commit(base_id)?;
+    repo.commit(Some("HEAD"), &sig, &sig, "docs", &tree, &[&base])?;
+
+    for args in [&[][..], &["release", "--dry-run"][..]] {
+        let output = nano(&path, args).output()?;
+        assert!(output.status.success(), "{output:?}");
+
+        let output = nano(&path, args)
+            .env("BASE_REF", "does-not-exist")
+            .output()?;
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("does-not-exist"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+fn nano(path: &Path, args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_nano"));
+    command
+        .args(args)
+        .current_dir(path)
+        .env_remove("BASE_REF")
+        .env_remove("PLUGINS_DIR");
+    command
+}
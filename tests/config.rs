@@ -0,0 +1,81 @@
+use nano::config::{BuildMode, Config, ConfigError, SymlinkPolicy};
+use std::time::Duration;
+
+#[test]
+fn defaults() {
+    let config = Config::parse("").unwrap();
+    assert_eq!(config.dist_dir, ".dist");
+    assert_eq!(config.languages, ["en", "zh_CN"]);
+    assert_eq!(
+        config.registry.base_url,
+        "https://bs-plugins.littleservice.cn/"
+    );
+    assert_eq!(config.registry.schema_version, 1);
+    assert_eq!(config.build.mode, BuildMode::Monorepo);
+    assert_eq!(config.build.source_extensions, ["ts", "tsx", "scss"]);
+    assert_eq!(
+        config.build.install_timeout(),
+        Some(Duration::from_secs(600))
+    );
+    assert_eq!(config.composer.timeout(), Some(Duration::from_secs(600)));
+}
+
+#[test]
+fn overrides() {
+    let config = Config::parse(
+        r#"
+dist_dir = "dist"
+languages = ["en", "zh_CN", "ja"]
+
+[registry]
+base_url = "https://example.com/plugins/"
+schema_version = 2
+
+[build]
+mode = "per_plugin"
+source_extensions = ["ts", "vue"]
+build_timeout = 0
+
+[zip]
+symlinks = "store"
+"#,
+    )
+    .unwrap();
+    assert_eq!(config.dist_dir, "dist");
+    assert_eq!(config.languages, ["en", "zh_CN", "ja"]);
+    assert_eq!(config.registry.base_url, "https://example.com/plugins/");
+    assert_eq!(config.registry.schema_version, 2);
+    assert_eq!(config.build.mode, BuildMode::PerPlugin);
+    assert_eq!(config.build.source_extensions, ["ts", "vue"]);
+    assert_eq!(config.build.build_timeout(), None);
+    assert_eq!(config.zip.symlinks, SymlinkPolicy::Store);
+    assert!(config.composer.bs_lock_url.ends_with("composer.lock"));
+}
+
+#[test]
+fn invalid_values() {
+    match Config::parse("languages = [\"zh_CN\"]") {
+        Err(ConfigError::Invalid { key, .. }) => assert_eq!(key, "languages"),
+        result => panic!("unexpected result: {result:?}"),
+    }
+
+    match Config::parse("[registry]\nbase_url = \"https://example.com\"") {
+        Err(ConfigError::Invalid { key, .. }) => assert_eq!(key, "registry.base_url"),
+        result => panic!("unexpected result: {result:?}"),
+    }
+
+    match Config::parse("[registry]\nschema_version = 3") {
+        Err(ConfigError::Invalid { key, .. }) => assert_eq!(key, "registry.schema_version"),
+        result => panic!("unexpected result: {result:?}"),
+    }
+
+    match Config::parse("[build]\nsource_extensions = [\".ts\"]") {
+        Err(ConfigError::Invalid { key, .. }) => assert_eq!(key, "build.source_extensions"),
+        result => panic!("unexpected result: {result:?}"),
+    }
+
+    match Config::parse("[registry]\nurl = \"https://example.com/\"") {
+        Err(ConfigError::Parse(e)) => assert!(e.to_string().contains("url")),
+        result => panic!("unexpected result: {result:?}"),
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::BTreeSet;
+
+use nano::directive::Directives;
+
+#[test]
+fn no_directives() {
+    let directives = Directives::parse("fix typo");
+    assert_eq!(directives, Directives::default());
+}
+
+#[test]
+fn force_update() {
+    let directives = Directives::parse("force update: yggdrasil-api");
+    assert!(directives.force_update.contains("yggdrasil-api"));
+    assert_eq!(directives.force_update.len(), 1);
+    assert!(!directives.force_update_all);
+
+    let directives =
+        Directives::parse("bump deps\n\nforce update: a, b\nforce update: c with comment");
+    assert_eq!(
+        directives.force_update.into_iter().collect::<Vec<_>>(),
+        vec!["a", "b", "c"]
+    );
+
+    let directives = Directives::parse("force update: *");
+    assert!(directives.force_update_all);
+    assert!(directives.force_update.is_empty());
+}
+
+#[test]
+fn skip() {
+    let directives = Directives::parse("skip build: a,b");
+    assert_eq!(
+        directives.skip_build.into_iter().collect::<Vec<_>>(),
+        vec!["a", "b"]
+    );
+    assert!(!directives.skip_run);
+
+    let directives = Directives::parse("[nano skip] update README");
+    assert!(directives.skip_run);
+}
+
+#[test]
+fn trailers() {
+    let directives = Directives::parse(
+        "fix bug\n\nNano-Force-Update: a, b\nNano-Skip-Build: c\nSigned-off-by: git <m@git.me>",
+    );
+    assert_eq!(
+        directives.force_update.into_iter().collect::<Vec<_>>(),
+        vec!["a", "b"]
+    );
+    assert_eq!(
+        directives.skip_build.into_iter().collect::<Vec<_>>(),
+        vec!["c"]
+    );
+    assert!(!directives.skip_run);
+
+    let directives = Directives::parse("docs\n\nNano-Skip: true");
+    assert!(directives.skip_run);
+}
+
+#[test]
+fn multiple_commits() {
+    let directives = Directives::parse_all(["force update: a", "skip build: b", "merge"]);
+    assert!(directives.force_update.contains("a"));
+    assert!(directives.skip_build.contains("b"));
+}
+
+#[test]
+fn scoped_to_commits() {
+    let none = BTreeSet::new();
+    let a = BTreeSet::from(["a".to_owned()]);
+
+    // Newest first, like commits returned by the analyzer.
+    let directives = Directives::parse_commits([("merge", &none), ("skip build: a", &a)]);
+    assert!(directives.skip_build.contains("a"));
+    let directives = Directives::parse_commits([("bump again", &a), ("skip build: a", &a)]);
+    assert!(directives.skip_build.is_empty());
+    let directives = Directives::parse_commits([("skip build: a", &a), ("bump", &a)]);
+    assert!(directives.skip_build.contains("a"));
+
+    assert!(Directives::parse_commits([("merge", &none), ("[nano skip] docs", &none)]).skip_run);
+    assert!(!Directives::parse_commits([("[nano skip] docs", &none), ("bump", &a)]).skip_run);
+    assert!(
+        !Directives::parse_commits([("force update: *", &none), ("[nano skip]", &none)]).skip_run
+    );
+    assert!(Directives::parse_commits([("[nano skip]", &a), ("[nano skip]", &none)]).skip_run);
+}
@@ -1,3 +1,5 @@
+use nano::config::Config;
+
 #[tokio::test]
 async fn top_level_string() {
     let text = nano::i18n::trans("./tests/i18n", "general.title", "en").await;
@@ -24,3 +26,42 @@ async fn failures() {
     let result = nano::i18n::trans("./tests/i18n", "general.yuuko", "en").await;
     assert_eq!(result, "general.yuuko");
 }
+
+#[tokio::test]
+async fn check_missing() {
+    let missing = nano::i18n::check(
+        "./tests/i18n",
+        ["demo"].iter(),
+        &Config::default().languages,
+    )
+    .await;
+    assert_eq!(missing.len(), 1);
+    assert!(missing[0].contains("'demo::general.title' in 'zh_CN'"));
+
+    let missing = nano::i18n::check(
+        "./tests/i18n",
+        ["nope"].iter(),
+        &Config::default().languages,
+    )
+    .await;
+    assert_eq!(missing.len(), 1);
+}
+
+#[tokio::test]
+async fn store_languages() {
+    let languages = [String::from("en"), String::from("fr")];
+    let store =
+        nano::i18n::I18nStore::create("./tests/i18n", ["demo", "translated"].iter(), &languages)
+            .await;
+
+    let demo = store.get("demo").unwrap();
+    assert_eq!(demo.title.get("en"), "Demo");
+    assert_eq!(demo.title.get("fr"), "Demo");
+    assert_eq!(demo.description.get("fr"), "A plain description.");
+
+    let translated = store.get("translated").unwrap();
+    let discovered = translated.title.languages().collect::<Vec<_>>();
+    assert_eq!(discovered, ["en", "ja"]);
+    assert_eq!(translated.title.get("ja"), "翻訳済み");
+    assert_eq!(translated.description.get("ja"), "A translated plugin.");
+}
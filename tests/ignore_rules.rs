@@ -0,0 +1,48 @@
+use nano::{config::Config, ignore_rules::IgnoreRules};
+use std::{env::temp_dir, io::ErrorKind};
+use tokio::{fs, io::Result};
+
+#[tokio::test]
+async fn defaults_and_nanoignore() -> Result<()> {
+    let mut path = temp_dir();
+    path.push("ignore_rules-test");
+
+    match fs::remove_dir_all(&path).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        e => panic!("{e:?}"),
+    };
+    fs::create_dir_all(&path).await?;
+    fs::write(path.join(".nanoignore"), "docs/\n!assets/types.d.ts\n").await?;
+
+    let rules = IgnoreRules::load(&path, &Config::default().build.source_extensions)?;
+
+    assert!(rules.is_ignored("node_modules", true));
+    assert!(rules.is_ignored(".github", true));
+    assert!(rules.is_ignored(".gitignore", false));
+    assert!(rules.is_ignored(".nanoignore", false));
+    assert!(rules.is_ignored("tests", true));
+    assert!(rules.is_ignored("assets/dist/app.js.map", false));
+    assert!(rules.is_ignored("assets/src/components/App.tsx", false));
+    assert!(rules.is_ignored("assets/style.scss", false));
+    assert!(rules.is_ignored("docs", true));
+
+    assert!(!rules.is_ignored("assets/types.d.ts", false));
+    assert!(!rules.is_ignored("assets/dist/app.js", false));
+    assert!(!rules.is_ignored("vendor/foo/bar/tests", true));
+    assert!(!rules.is_ignored("src/Listener.php", false));
+    assert!(!rules.is_ignored("package.json", false));
+
+    Ok(())
+}
+
+#[test]
+fn excluded_paths() {
+    let mut rules = IgnoreRules::load("/nonexistent", &[]).unwrap();
+    rules.exclude("vendor/illuminate/support");
+
+    assert!(rules.is_ignored("vendor/illuminate/support", true));
+    assert!(rules.is_ignored("vendor/illuminate/support/src/Str.php", false));
+    assert!(!rules.is_ignored("vendor/illuminate/support-extra", true));
+    assert!(!rules.is_ignored("vendor/illuminate", true));
+}
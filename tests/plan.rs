@@ -0,0 +1,61 @@
+use nano::{
+    analyzer::{Change, ChangeSet},
+    config::Config,
+    i18n::I18nStore,
+    plan::Plan,
+};
+use std::collections::BTreeMap;
+
+#[tokio::test]
+async fn display_plan() {
+    let mut changes = ChangeSet::default();
+    changes.insert(
+        "a".to_string(),
+        Change::Updated {
+            version: "1.1.0".to_string(),
+        },
+    );
+    changes.insert(
+        "b".to_string(),
+        Change::Renamed {
+            from: "c".to_string(),
+            version: "2.0.0".to_string(),
+        },
+    );
+    changes.insert("d".to_string(), Change::Removed);
+
+    let mut published = BTreeMap::new();
+    published.insert("a".to_string(), "1.0.0".to_string());
+    published.insert("c".to_string(), "1.0.0".to_string());
+    published.insert("d".to_string(), "0.1.0".to_string());
+
+    let i18n_store = I18nStore::create(
+        "./tests/plan",
+        changes.to_build().map(|(name, _)| name),
+        &[],
+    )
+    .await;
+    let plan = Plan::new(
+        &changes,
+        &published,
+        &i18n_store,
+        &Config::default().languages,
+    )
+    .to_string();
+
+    assert!(plan.contains("~ a: 1.0.0 -> 1.1.0"));
+    assert!(plan.contains("zip: a_1.1.0.zip"));
+    assert!(plan.contains("> c -> b: 1.0.0 -> 2.0.0"));
+    assert!(plan.contains("registry_zh_CN.json: remove 'c', add 'b'"));
+    assert!(plan.contains("- d: 0.1.0 -> (removed)"));
+    assert!(!plan.contains("d_"));
+}
+
+#[tokio::test]
+async fn empty_plan() {
+    let changes = ChangeSet::default();
+    let i18n_store = I18nStore::create("./tests/plan", std::iter::empty::<&str>(), &[]).await;
+    let plan = Plan::new(&changes, &BTreeMap::new(), &i18n_store, &[]);
+    assert!(plan.is_empty());
+    assert_eq!(plan.to_string(), "Nothing to release.\n");
+}
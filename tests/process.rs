@@ -0,0 +1,99 @@
+use nano::process::{run, StepError, StepErrorKind};
+use std::{env::temp_dir, io::ErrorKind, time::Duration};
+use tokio::{fs, process::Command};
+
+#[tokio::test]
+async fn capture_output() -> anyhow::Result<()> {
+    let mut log_dir = temp_dir();
+    log_dir.push("process-test-logs");
+
+    match fs::remove_dir_all(&log_dir).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        e => panic!("{e:?}"),
+    };
+
+    let mut command = Command::new("sh");
+    command.args(["-c", "echo out; echo err >&2"]);
+    run(&mut command, "echo", Some("test"), Some(&log_dir), None).await?;
+
+    let log = fs::read_to_string(log_dir.join("test.echo.log")).await?;
+    assert!(log.contains("out\n"));
+    assert!(log.contains("err\n"));
+
+    let mut command = Command::new("sh");
+    command.args(["-c", "echo failed >&2; exit 3"]);
+    let error = run(&mut command, "fail", None, Some(&log_dir), None)
+        .await
+        .unwrap_err();
+    let error = error
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<StepError>())
+        .unwrap();
+    assert_eq!(error.step, "fail");
+    match &error.kind {
+        StepErrorKind::Failed { code, output } => {
+            assert_eq!(*code, Some(3));
+            assert_eq!(output, "failed\n");
+        }
+        kind => panic!("unexpected error: {kind:?}"),
+    }
+    assert!(fs::metadata(log_dir.join("fail.log")).await.is_ok());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn time_out() {
+    let mut command = Command::new("sh");
+    command.args(["-c", "echo started; sleep 5"]);
+    let error = run(
+        &mut command,
+        "sleep",
+        Some("test"),
+        None,
+        Some(Duration::from_millis(200)),
+    )
+    .await
+    .unwrap_err();
+    let error = error
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<StepError>())
+        .unwrap();
+    assert_eq!(error.plugin.as_deref(), Some("test"));
+    match &error.kind {
+        StepErrorKind::TimedOut { timeout, .. } => {
+            assert_eq!(*timeout, Duration::from_millis(200))
+        }
+        kind => panic!("unexpected error: {kind:?}"),
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn kill_on_drop() -> anyhow::Result<()> {
+    let mut pid_file = temp_dir();
+    pid_file.push("process-test-kill-on-drop.pid");
+
+    let mut command = Command::new("sh");
+    command.args([
+        "-c",
+        &format!("sleep 30 & echo $! > {}; wait", pid_file.display()),
+    ]);
+    let result = tokio::time::timeout(
+        Duration::from_millis(500),
+        run(&mut command, "sleep", None, None, None),
+    )
+    .await;
+    assert!(result.is_err());
+
+    let pid = fs::read_to_string(&pid_file).await?;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    // The orphaned `sleep` is either reaped or left as a zombie.
+    match fs::read_to_string(format!("/proc/{}/stat", pid.trim())).await {
+        Ok(stat) => assert!(stat.contains(") Z "), "still running: {stat}"),
+        Err(e) => assert_eq!(e.kind(), ErrorKind::NotFound),
+    }
+
+    Ok(())
+}
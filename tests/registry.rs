@@ -0,0 +1,347 @@
+use nano::{
+    config::Config,
+    i18n::I18nStore,
+    registry::{self, parse_registry, serialize_registry, RegistryError},
+    zip::Archive,
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, env::temp_dir, io::ErrorKind};
+use tokio::fs;
+
+fn dist(name: &str, version: &str) -> Value {
+    json!({
+        "type": "zip",
+        "url": format!("https://example.com/{name}_{version}.zip"),
+        "shasum": "abc",
+    })
+}
+
+#[test]
+fn migrate_v1() {
+    let v1 = json!({
+        "version": 1,
+        "packages": [{
+            "name": "demo",
+            "version": "1.2.0",
+            "title": "Demo",
+            "description": "A demo.",
+            "author": "me",
+            "require": { "blessing-skin-server": "^6.0.0" },
+            "dist": dist("demo", "1.2.0"),
+        }],
+    });
+    let packages = parse_registry(&v1.to_string()).unwrap();
+
+    let demo = &packages["demo"];
+    assert_eq!(demo.title, "Demo");
+    assert_eq!(demo.versions.len(), 1);
+    let latest = demo.latest().unwrap();
+    assert_eq!(latest.version, "1.2.0");
+    assert_eq!(latest.require["blessing-skin-server"], "^6.0.0");
+    assert_eq!(latest.released_at, None);
+
+    let v2 = serde_json::from_slice::<Value>(&serialize_registry(&packages, 2)).unwrap();
+    assert_eq!(v2["version"], 2);
+    assert_eq!(v2["packages"][0]["versions"][0]["version"], "1.2.0");
+    assert!(v2["packages"][0]["versions"][0]
+        .get("released_at")
+        .is_none());
+}
+
+#[test]
+fn write_v1_from_v2() {
+    let v2 = json!({
+        "version": 2,
+        "packages": [{
+            "name": "demo",
+            "title": "Demo",
+            "description": "A demo.",
+            "author": "me",
+            "versions": [
+                {
+                    "version": "1.10.0",
+                    "require": {},
+                    "dist": dist("demo", "1.10.0"),
+                    "released_at": "2023-02-01T00:00:00Z",
+                },
+                {
+                    "version": "1.9.0",
+                    "require": {},
+                    "dist": dist("demo", "1.9.0"),
+                    "released_at": "2023-01-01T00:00:00Z",
+                },
+            ],
+        }],
+    });
+    let packages = parse_registry(&v2.to_string()).unwrap();
+    assert_eq!(packages["demo"].versions.len(), 2);
+
+    let v1 = serde_json::from_slice::<Value>(&serialize_registry(&packages, 1)).unwrap();
+    assert_eq!(v1["version"], 1);
+    let demo = &v1["packages"][0];
+    assert_eq!(demo["version"], "1.10.0");
+    assert_eq!(demo["dist"]["url"], "https://example.com/demo_1.10.0.zip");
+    assert!(demo.get("versions").is_none());
+
+    assert!(parse_registry(r#"{"version":3,"packages":[]}"#).is_err());
+}
+
+#[tokio::test]
+async fn rebuild() -> anyhow::Result<()> {
+    let mut path = temp_dir();
+    path.push("registry-rebuild-test");
+
+    match fs::remove_dir_all(&path).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        e => panic!("{e:?}"),
+    };
+    fs::create_dir_all(&path).await?;
+
+    let package = |name: &str, version: &str, shasum: &str| {
+        json!({
+            "name": name,
+            "title": name,
+            "description": "",
+            "author": "",
+            "versions": [{
+                "version": version,
+                "require": {},
+                "dist": {
+                    "type": "zip",
+                    "url": format!("https://example.com/{name}_{version}.zip"),
+                    "shasum": shasum,
+                },
+                "released_at": "2019-01-01T00:00:00Z",
+            }],
+        })
+    };
+    let previous = json!({
+        "version": 2,
+        "packages": [
+            package("demo", "0.9.0", "abc"),
+            package("gone", "1.0.0", "abc"),
+            package("translated", "1.0.0", "old"),
+        ],
+    });
+    fs::write(path.join("registry_en.json"), previous.to_string()).await?;
+
+    let mut archives = BTreeMap::new();
+    for name in ["demo", "translated"] {
+        let file = path.join(format!("{name}_1.0.0.zip"));
+        fs::write(&file, name).await?;
+        archives.insert(name.to_owned(), Archive::read(&file)?);
+    }
+
+    let plugins = ["demo", "nozip", "translated"]
+        .into_iter()
+        .map(|name| (name.to_owned(), String::from("1.0.0")))
+        .collect::<BTreeMap<_, _>>();
+    let mut config = Config::default();
+    config.registry.schema_version = 2;
+    let i18n_store = I18nStore::create("tests/i18n", plugins.keys(), &config.languages).await;
+    let dist_dir = path.display().to_string();
+    registry::rebuild_registry(
+        &dist_dir,
+        "tests/i18n",
+        &plugins,
+        &archives,
+        1_600_000_000,
+        &i18n_store,
+        &config,
+    )
+    .await?;
+
+    let en = parse_registry(&fs::read_to_string(path.join("registry_en.json")).await?)?;
+    assert_eq!(en.keys().collect::<Vec<_>>(), ["demo", "translated"]);
+    assert!(en
+        .values()
+        .flat_map(|package| &package.versions)
+        .all(|release| !release.dist.shasum.is_empty()));
+
+    let demo = &en["demo"];
+    let versions = demo
+        .versions
+        .iter()
+        .map(|release| release.version.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(versions, ["1.0.0", "0.9.0"]);
+    let latest = demo.latest().unwrap();
+    assert_eq!(latest.dist.shasum, archives["demo"].sha256);
+    assert_eq!(latest.released_at.as_deref(), Some("2020-09-13T12:26:40Z"));
+    assert_eq!(
+        demo.versions[1].released_at.as_deref(),
+        Some("2019-01-01T00:00:00Z")
+    );
+
+    let translated = &en["translated"];
+    assert_eq!(translated.title, "Translated");
+    assert_eq!(translated.author, "The Blessing Skin Team");
+    let release = translated.latest().unwrap();
+    assert_eq!(release.dist.shasum, archives["translated"].sha256);
+    assert_eq!(release.released_at.as_deref(), Some("2019-01-01T00:00:00Z"));
+
+    let zh_cn = parse_registry(&fs::read_to_string(path.join("registry_zh_CN.json")).await?)?;
+    assert_eq!(zh_cn.keys().collect::<Vec<_>>(), ["demo", "translated"]);
+    assert_eq!(zh_cn["demo"].versions.len(), 1);
+    assert_eq!(
+        zh_cn["translated"].latest().unwrap().dist.shasum,
+        archives["translated"].sha256
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn verify_consistency() -> anyhow::Result<()> {
+    let mut path = temp_dir();
+    path.push("registry-verify-test");
+
+    match fs::remove_dir_all(&path).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        e => panic!("{e:?}"),
+    };
+    fs::create_dir_all(&path).await?;
+
+    fs::write(path.join("demo_1.0.0.zip"), b"demo").await?;
+    fs::write(path.join("other_1.0.0.zip"), b"other").await?;
+    fs::write(path.join("old_0.1.0.zip"), b"old").await?;
+    let shasum = format!("{:x}", Sha256::digest(b"demo"));
+
+    let release = |name: &str, shasum: &str| {
+        json!({
+            "version": "1.0.0",
+            "require": {},
+            "dist": {
+                "type": "zip",
+                "url": format!("https://example.com/{name}_1.0.0.zip"),
+                "shasum": shasum,
+            },
+        })
+    };
+    let package = |name: &str, versions: Vec<Value>| {
+        json!({
+            "name": name,
+            "title": name,
+            "description": "",
+            "author": "",
+            "versions": versions,
+        })
+    };
+    let en = json!({
+        "version": 2,
+        "packages": [
+            package("demo", vec![release("demo", &shasum)]),
+            package("other", vec![release("other", "abc")]),
+            package("missing", vec![release("missing", "abc")]),
+        ],
+    });
+    let zh_cn = json!({
+        "version": 2,
+        "packages": [package("demo", vec![release("demo", &shasum)])],
+    });
+    fs::write(path.join("registry_en.json"), en.to_string()).await?;
+    fs::write(path.join("registry_zh_CN.json"), zh_cn.to_string()).await?;
+
+    let dist_dir = path.display().to_string();
+    let languages = Config::default().languages;
+    let errors = registry::verify(&dist_dir, &languages).await?;
+    let messages = errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+    assert_eq!(errors.len(), 5, "{messages:#?}");
+    assert!(matches!(
+        &errors[0],
+        RegistryError::MissingRelease { name, languages, .. }
+            if name == "missing" && languages == &["zh_CN"]
+    ));
+    assert!(matches!(
+        &errors[1],
+        RegistryError::MissingArchive { file, .. } if file == "missing_1.0.0.zip"
+    ));
+    assert!(matches!(
+        &errors[2],
+        RegistryError::MissingRelease { name, .. } if name == "other"
+    ));
+    assert!(matches!(
+        &errors[3],
+        RegistryError::DigestMismatch { name, expected, .. } if name == "other" && expected == "abc"
+    ));
+    assert!(matches!(
+        &errors[4],
+        RegistryError::Orphaned { file } if file == "old_0.1.0.zip"
+    ));
+
+    fs::remove_file(path.join("old_0.1.0.zip")).await?;
+    fs::write(path.join("registry_en.json"), zh_cn.to_string()).await?;
+    fs::remove_file(path.join("other_1.0.0.zip")).await?;
+    assert!(registry::verify(&dist_dir, &languages).await?.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn backup_and_rollback() -> anyhow::Result<()> {
+    let mut path = temp_dir();
+    path.push("registry-rollback-test");
+
+    match fs::remove_dir_all(&path).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        e => panic!("{e:?}"),
+    };
+    fs::create_dir_all(&path).await?;
+
+    let previous = json!({ "version": 2, "packages": [] }).to_string();
+    fs::write(path.join("registry_en.json"), &previous).await?;
+
+    let plugins = BTreeMap::from([(String::from("demo"), String::from("1.0.0"))]);
+    let config = Config::default();
+    let i18n_store = I18nStore::create("tests/i18n", plugins.keys(), &config.languages).await;
+    let zip = path.join("demo_1.0.0.zip");
+    fs::write(&zip, b"demo").await?;
+    let archives = BTreeMap::from([(String::from("demo"), Archive::read(&zip)?)]);
+    let dist_dir = path.display().to_string();
+    registry::rebuild_registry(
+        &dist_dir,
+        "tests/i18n",
+        &plugins,
+        &archives,
+        1_600_000_000,
+        &i18n_store,
+        &config,
+    )
+    .await?;
+
+    let mut files = vec![];
+    let mut entries = fs::read_dir(&path).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        files.push(entry.file_name().to_string_lossy().into_owned());
+    }
+    files.sort();
+    assert_eq!(
+        files,
+        [
+            "backups",
+            "demo_1.0.0.zip",
+            "registry_en.json",
+            "registry_zh_CN.json"
+        ]
+    );
+    let en = parse_registry(&fs::read_to_string(path.join("registry_en.json")).await?)?;
+    assert!(en.contains_key("demo"));
+
+    registry::rollback(&dist_dir, &config.languages).await?;
+    assert_eq!(
+        fs::read_to_string(path.join("registry_en.json")).await?,
+        previous
+    );
+    assert!(!path.join("registry_zh_CN.json").exists());
+
+    let error = registry::rollback(&dist_dir, &config.languages)
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::NotFound);
+
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use nano::version::{check, VersionError};
+
+#[test]
+fn upgrades() {
+    assert!(check("a", None, "1.0.0", false).is_ok());
+    assert!(check("a", Some("1.0.0"), "1.0.1", false).is_ok());
+    assert!(check("a", Some("1.0.0-beta.1"), "1.0.0", false).is_ok());
+}
+
+#[test]
+fn invalid_version() {
+    assert!(matches!(
+        check("a", None, "1.0", false),
+        Err(VersionError::Invalid { .. })
+    ));
+    assert!(matches!(
+        check("a", Some("1.0.0"), "v1.1.0", true),
+        Err(VersionError::Invalid { .. })
+    ));
+}
+
+#[test]
+fn unchanged_or_downgrade() {
+    assert!(matches!(
+        check("a", Some("1.0.0"), "1.0.0", false),
+        Err(VersionError::Unchanged { .. })
+    ));
+    assert!(matches!(
+        check("a", Some("1.2.0"), "1.1.0", false),
+        Err(VersionError::Downgrade { .. })
+    ));
+
+    assert!(check("a", Some("1.0.0"), "1.0.0", true).is_ok());
+    assert!(check("a", Some("1.2.0"), "1.1.0", true).is_ok());
+}
@@ -0,0 +1,231 @@
+use nano::{
+    config::{Config, SymlinkPolicy},
+    ignore_rules::IgnoreRules,
+    zip::{create_zip, stage, verify, ArchiveErrorKind, ArchiveOptions, StagedKind},
+};
+use sha2::{Digest, Sha256};
+use std::{
+    env::temp_dir,
+    fs::{self, File},
+    io::ErrorKind,
+    time::{Duration, SystemTime},
+};
+use zip::ZipArchive;
+
+#[test]
+fn reproducible() -> anyhow::Result<()> {
+    let mut path = temp_dir();
+    path.push("zip-reproducible-test");
+
+    match fs::remove_dir_all(&path) {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        e => panic!("{e:?}"),
+    };
+
+    let plugin = path.join("demo");
+    fs::create_dir_all(plugin.join("src"))?;
+    fs::write(plugin.join("package.json"), b"{}")?;
+    // Hardly compressible and larger than buffers, so the digest isn't only taken of small writes.
+    let mut seed = 1u32;
+    let noise = (0..256 * 1024)
+        .map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) as u8
+        })
+        .collect::<Vec<_>>();
+    fs::write(plugin.join("src/b.php"), noise)?;
+    fs::write(plugin.join("src/a.php"), b"<?php")?;
+
+    let rules = IgnoreRules::load(&plugin, &[])?;
+    let options = ArchiveOptions::new(1_600_000_000);
+
+    let first = path.join("first.zip");
+    let archive = create_zip(
+        &stage(&plugin, &rules, SymlinkPolicy::Follow)?,
+        &first,
+        &options,
+    )?;
+    let bytes = fs::read(&first)?;
+    assert!(!path.join("first.zip.tmp").exists());
+    assert_eq!(archive.path, first);
+    assert_eq!(archive.size, bytes.len() as u64);
+    assert_eq!(archive.sha256, format!("{:x}", Sha256::digest(&bytes)));
+
+    let modified = SystemTime::now() - Duration::from_secs(86400);
+    File::options()
+        .write(true)
+        .open(plugin.join("src/a.php"))?
+        .set_modified(modified)?;
+    let second = create_zip(
+        &stage(&plugin, &rules, SymlinkPolicy::Follow)?,
+        path.join("second.zip"),
+        &options,
+    )?;
+
+    assert_eq!(bytes, fs::read(&second.path)?);
+    assert_eq!(archive.sha256, second.sha256);
+
+    let mut archive = ZipArchive::new(File::open(&first)?)?;
+    let names = (0..archive.len())
+        .map(|index| archive.by_index(index).map(|entry| entry.name().to_owned()))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(
+        names,
+        [
+            "demo/",
+            "demo/package.json",
+            "demo/src/",
+            "demo/src/a.php",
+            "demo/src/b.php"
+        ]
+    );
+    let entry = archive.by_name("demo/src/a.php")?;
+    let modified = entry.last_modified();
+    assert_eq!(
+        (modified.year(), modified.month(), modified.day()),
+        (2020, 9, 13)
+    );
+    assert_eq!(entry.unix_mode().map(|mode| mode & 0o777), Some(0o644));
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn permissions_and_symlinks() -> anyhow::Result<()> {
+    use std::{
+        io::Read,
+        os::unix::fs::{symlink, PermissionsExt},
+    };
+
+    let mut path = temp_dir();
+    path.push("zip-symlinks-test");
+
+    match fs::remove_dir_all(&path) {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        e => panic!("{e:?}"),
+    };
+
+    let plugin = path.join("demo");
+    fs::create_dir_all(plugin.join("bin"))?;
+    fs::write(plugin.join("bin/cli"), b"#!/bin/sh\necho hi\n")?;
+    fs::set_permissions(plugin.join("bin/cli"), fs::Permissions::from_mode(0o755))?;
+    fs::write(plugin.join("README.md"), b"demo")?;
+    symlink("bin/cli", plugin.join("cli"))?;
+
+    let rules = IgnoreRules::load(&plugin, &[])?;
+    let options = ArchiveOptions::new(1_600_000_000);
+
+    let files = stage(&plugin, &rules, SymlinkPolicy::Store)?;
+    let dest = path.join("store.zip");
+    create_zip(&files, &dest, &options)?;
+
+    let mut archive = ZipArchive::new(File::open(&dest)?)?;
+    assert_eq!(archive.len(), files.len() + 2);
+    for file in &files {
+        let mut entry = archive.by_name(&file.name)?;
+        let mut content = vec![];
+        entry.read_to_end(&mut content)?;
+        let mode = entry.unix_mode().unwrap();
+        match &file.kind {
+            StagedKind::File { executable } => {
+                assert_eq!(content, fs::read(&file.source)?);
+                let expected = if *executable { 0o100755 } else { 0o100644 };
+                assert_eq!(mode, expected, "mode of '{}'", file.name);
+            }
+            StagedKind::Symlink { target } => {
+                assert_eq!(content, target.as_bytes());
+                assert_eq!(mode & 0o170000, 0o120000);
+            }
+        }
+    }
+    assert_eq!(
+        files.iter().map(|file| &file.kind).collect::<Vec<_>>(),
+        [
+            &StagedKind::File { executable: false },
+            &StagedKind::File { executable: true },
+            &StagedKind::Symlink {
+                target: String::from("bin/cli")
+            },
+        ]
+    );
+
+    let files = stage(&plugin, &rules, SymlinkPolicy::Follow)?;
+    assert_eq!(
+        files.last().map(|file| &file.kind),
+        Some(&StagedKind::File { executable: true })
+    );
+
+    assert!(stage(&plugin, &rules, SymlinkPolicy::Reject).is_err());
+
+    symlink("../../outside", plugin.join("bin/escape"))?;
+    assert!(stage(&plugin, &rules, SymlinkPolicy::Store).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn verify_archive() -> anyhow::Result<()> {
+    let mut path = temp_dir();
+    path.push("zip-verify-test");
+
+    match fs::remove_dir_all(&path) {
+        Ok(_) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        e => panic!("{e:?}"),
+    };
+
+    let plugin = path.join("demo");
+    fs::create_dir_all(&plugin)?;
+    fs::write(
+        plugin.join("package.json"),
+        br#"{"name":"demo","version":"1.0.0"}"#,
+    )?;
+    fs::write(plugin.join(".env"), b"SECRET=1")?;
+    fs::write(plugin.join(".nanoignore"), b"!.env")?;
+
+    let rules = IgnoreRules::load(&plugin, &[])?;
+    let dest = path.join("demo_1.0.0.zip");
+    create_zip(
+        &stage(&plugin, &rules, SymlinkPolicy::Follow)?,
+        &dest,
+        &ArchiveOptions::new(0),
+    )?;
+
+    let config = Config::default().zip;
+    let errors = verify(&dest, "demo", "1.0.0", &config);
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(
+        &errors[0].kind,
+        ArchiveErrorKind::Forbidden { entry } if entry == "demo/.env"
+    ));
+
+    let errors = verify(&dest, "other", "1.0.1", &config);
+    assert!(errors
+        .iter()
+        .any(|error| matches!(error.kind, ArchiveErrorKind::OutsideRoot { .. })));
+    assert!(errors
+        .iter()
+        .any(|error| matches!(error.kind, ArchiveErrorKind::MissingManifest)));
+
+    fs::remove_file(plugin.join(".env"))?;
+    create_zip(
+        &stage(&plugin, &rules, SymlinkPolicy::Follow)?,
+        &dest,
+        &ArchiveOptions::new(0),
+    )?;
+    assert!(verify(&dest, "demo", "1.0.0", &config).is_empty());
+
+    let errors = verify(&dest, "demo", "1.0.1", &config);
+    assert!(matches!(
+        &errors[..],
+        [error] if matches!(
+            &error.kind,
+            ArchiveErrorKind::ManifestMismatch { field: "version", .. }
+        )
+    ));
+
+    Ok(())
+}